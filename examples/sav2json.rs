@@ -0,0 +1,86 @@
+//! Prints a JSON summary of a save file.
+//!
+//! ```text
+//! cargo run --example sav2json -- test_files/new_world.sav
+//! ```
+
+use satisfactory_save_file::SaveFile;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: sav2json <save file>");
+            std::process::exit(2);
+        }
+    };
+
+    let save_file = SaveFile::parse(&mut BufReader::new(File::open(path)?))?;
+
+    let mut type_counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for object in &save_file.save_objects {
+        *type_counts.entry(object.type_path()).or_default() += 1;
+    }
+
+    println!("{{");
+    println!(
+        "  \"session_name\": {},",
+        json_string(&save_file.session_name)
+    );
+    println!("  \"save_version\": {},", save_file.save_version);
+    println!("  \"build_version\": {},", save_file.build_version);
+    println!("  \"world_type\": {},", json_string(&save_file.world_type));
+    println!(
+        "  \"start_loc\": {},",
+        json_string(&save_file.world_properties.start_loc)
+    );
+    println!(
+        "  \"visibility\": {},",
+        json_string(&save_file.session_visibility.to_string())
+    );
+    println!(
+        "  \"play_time_seconds\": {},",
+        save_file.play_time.num_seconds()
+    );
+    println!(
+        "  \"save_date\": {},",
+        json_string(&save_file.save_date.to_rfc3339())
+    );
+    println!("  \"is_modded_save\": {},", save_file.is_modded_save);
+    println!("  \"entity_count\": {},", save_file.entities().count());
+    println!("  \"component_count\": {},", save_file.components().count());
+    println!(
+        "  \"collected_object_count\": {},",
+        save_file.collected_objects.len()
+    );
+    println!("  \"type_counts\": {{");
+    for (i, (type_path, count)) in type_counts.iter().enumerate() {
+        let separator = if i + 1 < type_counts.len() { "," } else { "" };
+        println!("    {}: {}{}", json_string(type_path), count, separator);
+    }
+    println!("  }}");
+    println!("}}");
+    Ok(())
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
@@ -1,7 +1,7 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
-use satisfactory_save_file::read_string;
+use satisfactory_save_file::{read_string, slice_reader::SliceReader};
 
 fuzz_target!(|data: &[u8]| {
-    let _ = read_string(&mut &data.to_vec()[..]);
+    let _ = SliceReader::new(data).parse(read_string);
 });
\ No newline at end of file
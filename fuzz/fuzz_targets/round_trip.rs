@@ -0,0 +1,28 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use satisfactory_save_file::{LenientParse, SaveFile};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    // A partial parse can have fewer object data blobs than objects, which cannot be written
+    let save_file = match SaveFile::parse_lenient(&mut Cursor::new(data)) {
+        Ok(LenientParse {
+            save_file,
+            partial: false,
+            ..
+        }) => save_file,
+        _ => return,
+    };
+
+    let mut written = Cursor::new(Vec::new());
+    if save_file.write(&mut written).is_err() {
+        return;
+    }
+    written.set_position(0);
+    let parsed = SaveFile::parse(&mut written).expect("written save file does not parse");
+    // Sizes are not compared because write() may store strings in a different encoding
+    assert_eq!(parsed.header(), save_file.header());
+    assert_eq!(parsed.save_objects, save_file.save_objects);
+    assert_eq!(parsed.object_data, save_file.object_data);
+    assert_eq!(parsed.collected_objects, save_file.collected_objects);
+});
@@ -1,7 +1,7 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
-use satisfactory_save_file::SaveObject;
+use satisfactory_save_file::{slice_reader::SliceReader, SaveObject};
 
 fuzz_target!(|data: &[u8]| {
-    let _ = SaveObject::parse(&mut &data.to_vec()[..]);
+    let _ = SliceReader::new(data).parse(SaveObject::parse);
 });
\ No newline at end of file
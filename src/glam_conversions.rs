@@ -0,0 +1,80 @@
+//! Conversions between the vector types and `glam`, enabled by the `glam` feature.
+
+use crate::{Vector2, Vector3, Vector4};
+
+impl From<Vector2> for glam::Vec2 {
+    fn from(v: Vector2) -> Self {
+        glam::Vec2::new(v.x, v.y)
+    }
+}
+
+impl From<glam::Vec2> for Vector2 {
+    fn from(v: glam::Vec2) -> Self {
+        Vector2 { x: v.x, y: v.y }
+    }
+}
+
+/// ```
+/// use satisfactory_save_file::Vector3;
+///
+/// let position = Vector3 { x: 1.0, y: 2.0, z: 3.0 };
+/// let v: glam::Vec3 = position.into();
+/// assert_eq!(Vector3::from(v * 2.0), Vector3 { x: 2.0, y: 4.0, z: 6.0 });
+/// ```
+impl From<Vector3> for glam::Vec3 {
+    fn from(v: Vector3) -> Self {
+        glam::Vec3::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<glam::Vec3> for Vector3 {
+    fn from(v: glam::Vec3) -> Self {
+        Vector3 {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
+impl From<Vector4> for glam::Vec4 {
+    fn from(v: Vector4) -> Self {
+        glam::Vec4::new(v.x, v.y, v.z, v.w)
+    }
+}
+
+impl From<glam::Vec4> for Vector4 {
+    fn from(v: glam::Vec4) -> Self {
+        Vector4 {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+            w: v.w,
+        }
+    }
+}
+
+/// Entity rotations are stored as quaternions in `Vector4`.
+///
+/// ```
+/// use satisfactory_save_file::Vector4;
+///
+/// let rotation = Vector4 { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+/// assert_eq!(glam::Quat::from(rotation), glam::Quat::IDENTITY);
+/// ```
+impl From<Vector4> for glam::Quat {
+    fn from(v: Vector4) -> Self {
+        glam::Quat::from_xyzw(v.x, v.y, v.z, v.w)
+    }
+}
+
+impl From<glam::Quat> for Vector4 {
+    fn from(q: glam::Quat) -> Self {
+        Vector4 {
+            x: q.x,
+            y: q.y,
+            z: q.z,
+            w: q.w,
+        }
+    }
+}
@@ -1,15 +1,26 @@
 //! `SaveFile` represents save files in Satisfactory. Use `SaveFile::parse()` to read save files.
 
+use crate::position_tracking::PositionTracking;
 use crate::zlib_reader::ChunkedZLibReader;
 use crate::SessionVisiblity::{SvFriendsOnly, SvInvalid, SvPrivate};
-use anyhow::{Error, Result};
-use byteorder::{LittleEndian as L, ReadBytesExt};
+use byteorder::{LittleEndian as L, ReadBytesExt, WriteBytesExt};
 use chrono::{DateTime, Duration, TimeZone, Utc};
-use std::collections::HashMap;
-use std::convert::TryInto;
-use std::io::{Read, Seek};
+pub use flate2::Compression;
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 
+#[cfg(feature = "glam")]
+mod glam_conversions;
+#[cfg(feature = "trace")]
+mod parse_times;
+mod position_tracking;
+pub mod save_index;
+pub mod save_writer;
+pub mod slice_reader;
 pub mod zlib_reader;
+pub mod zlib_writer;
 
 /// Satisfactory save file.
 #[derive(Debug, Clone, PartialEq)]
@@ -20,6 +31,8 @@ pub struct SaveFile {
     pub world_type: String,
     pub world_properties: WorldProperties,
     pub session_name: String,
+    /// Stored in the header as whole seconds in an i32. Use `set_play_time()` to set it to a
+    /// value that can be stored.
     pub play_time: Duration,
     pub save_date: DateTime<Utc>,
     pub session_visibility: SessionVisiblity,
@@ -27,59 +40,857 @@ pub struct SaveFile {
     pub mod_meta_data: String,
     pub is_modded_save: bool,
     pub save_objects: Vec<SaveObject>,
+    /// Undecoded data of each object in `save_objects`, in the same order.
+    pub object_data: Vec<Vec<u8>>,
+    /// References to objects that the game destroyed.
+    pub collected_objects: Vec<ObjectReference>,
+    /// Total compressed length of the body chunks that were read. 0 if the body was not parsed.
+    pub compressed_size: u64,
+    /// Number of decompressed body bytes that were read, not counting the body length prefix.
+    pub decompressed_size: u64,
+    /// Length of the uncompressed header that precedes the first body chunk. 0 if the save was not
+    /// parsed. The header can be rewritten in place of these bytes without touching the body.
+    pub header_byte_len: u64,
 }
 
 impl SaveFile {
+    /// The only save header version whose field layout is known.
+    pub const SAVE_HEADER_VERSION: i32 = 8;
+    /// The save version that this crate has been tested with.
+    pub const TESTED_SAVE_VERSION: i32 = 25;
+
+    /// Versions that this crate has been tested with.
+    pub fn supported_versions() -> &'static [SupportedVersions] {
+        const SUPPORTED_VERSIONS: &[SupportedVersions] = &[SupportedVersions {
+            save_header: SaveFile::SAVE_HEADER_VERSION,
+            save_versions: SaveFile::TESTED_SAVE_VERSION..=SaveFile::TESTED_SAVE_VERSION,
+            build_versions: BuildVersion::OLDEST_TESTED..=BuildVersion::NEWEST_TESTED,
+        }];
+        SUPPORTED_VERSIONS
+    }
+
+    /// Returns true if the versions are in `supported_versions()`. Use `peek_header()` to check a
+    /// file before parsing it.
+    pub fn is_supported_version(save_header: i32, save_version: i32) -> bool {
+        SaveFile::supported_versions().iter().any(|supported| {
+            supported.save_header == save_header && supported.save_versions.contains(&save_version)
+        })
+    }
+
     /// Reads satisfactory save file to SaveFile struct.
     ///
     /// Save files are stored in `%localappdata%\FactoryGame\Saved\SaveGames\<your id>` and it has a
     /// `.sav` extension.
     ///
-    /// Tested with build version 152331.
-    ///
-    /// Do not pass a BufReader. I don't know why this fails with BufReader. Create an issue if you
-    /// figured it out.
+    /// See `supported_versions()` for the versions this has been tested with. Only save header
+    /// version 8 is supported; other header versions lay out the header fields differently and
+    /// return an error. An incomplete file returns `ParseError::Truncated`.
     pub fn parse<R>(file: &mut R) -> Result<SaveFile>
     where
         R: Read + Seek,
+    {
+        SaveFile::parse_with_options(file, &ParseOptions::default())
+    }
+
+    /// Same as `parse()` but also accepts gzip-compressed save files such as `.sav.gz`. The gzip
+    /// magic number is checked without consuming it, and input without it is parsed as a regular
+    /// save file. Gzip input is decompressed into memory before parsing.
+    pub fn parse_auto<R>(file: &mut R) -> Result<SaveFile>
+    where
+        R: Read + Seek,
+    {
+        const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+        let start = file.stream_position()?;
+        let mut magic = [0_u8; 2];
+        let is_gzip = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+        file.seek(SeekFrom::Start(start))?;
+
+        if is_gzip {
+            let mut data = Vec::new();
+            flate2::read::GzDecoder::new(file).read_to_end(&mut data)?;
+            SaveFile::parse(&mut Cursor::new(data))
+        } else {
+            SaveFile::parse(file)
+        }
+    }
+
+    /// Same as `parse()` but does not require `Seek`, so it can read from a stream such as a
+    /// network socket. The input is read strictly forward and not past the end of the last chunk
+    /// that the body needs.
+    ///
+    /// Unlike `parse()`, an incomplete file returns the error that the parser ran into instead of
+    /// `ParseError::Truncated`, because finding the expected length requires seeking.
+    pub fn parse_stream<R>(reader: &mut R) -> Result<SaveFile>
+    where
+        R: Read,
+    {
+        let (result, _) = SaveFile::read_file(reader, &ParseOptions::default(), false)?;
+        Ok(result.save_file)
+    }
+
+    /// Same as `parse()` but with options that control what is read.
+    pub fn parse_with_options<R>(file: &mut R, options: &ParseOptions) -> Result<SaveFile>
+    where
+        R: Read + Seek,
+    {
+        let (result, _) = SaveFile::read_file_checked(file, options, false)?;
+        Ok(result.save_file)
+    }
+
+    /// Same as `read_file()` but returns `ParseError::Truncated` for an incomplete file.
+    fn read_file_checked<R>(
+        file: &mut R,
+        options: &ParseOptions,
+        keep_partial: bool,
+    ) -> Result<(LenientParse, ParseStats)>
+    where
+        R: Read + Seek,
+    {
+        let start = file.stream_position()?;
+        match SaveFile::read_file(file, options, keep_partial) {
+            Ok((mut result, stats)) => {
+                result.error = result
+                    .error
+                    .map(|e| SaveFile::check_truncated(file, start).unwrap_or(e));
+                Ok((result, stats))
+            }
+            Err(e) => Err(SaveFile::check_truncated(file, start).unwrap_or(e)),
+        }
+    }
+
+    /// Reads the header and the body. Every parse function goes through here.
+    ///
+    /// If `keep_partial` is false, any error is returned as `Err`. If it is true, an error in the
+    /// compressed body is returned in `LenientParse::error` next to the objects that were read
+    /// before it, and data after the collected objects is ignored.
+    fn read_file<R>(
+        file: &mut R,
+        options: &ParseOptions,
+        keep_partial: bool,
+    ) -> Result<(LenientParse, ParseStats)>
+    where
+        R: Read,
+    {
+        let mut save_file = SaveFile::parse_header(file)?;
+        let mut stats = ParseStats::default();
+        let result = ChunkedZLibReader::new(file).and_then(|mut decoder| {
+            let body_length = decoder.body_length();
+            let result =
+                SaveFile::parse_objects(&mut decoder, body_length, &mut save_file, options)
+                    .and_then(|()| {
+                        if keep_partial {
+                            Ok(())
+                        } else {
+                            SaveFile::check_trailing_data(&decoder)
+                        }
+                    });
+            save_file.set_body_sizes(&decoder);
+            stats = ParseStats::new(&decoder, save_file.save_objects.len());
+            result
+        });
+
+        match result {
+            Err(e) if !keep_partial => Err(e),
+            result => Ok((
+                LenientParse {
+                    save_file,
+                    partial: result.is_err(),
+                    error: result.err(),
+                },
+                stats,
+            )),
+        }
+    }
+
+    /// Returns `ParseError::Truncated` if the file that starts at `start` is shorter than its header
+    /// and chunk headers declare. Called after an error to give a more useful one.
+    fn check_truncated<R>(file: &mut R, start: u64) -> Option<ParseError>
+    where
+        R: Read + Seek,
+    {
+        let end = file.seek(SeekFrom::End(0)).ok()?;
+        file.seek(SeekFrom::Start(start)).ok()?;
+        let bytes_available = end.saturating_sub(start);
+
+        match SaveFile::read_header(file) {
+            Ok(_) => {}
+            Err(ParseError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Some(ParseError::Truncated {
+                    stage: TruncatedStage::Header,
+                    bytes_expected: bytes_available + 1,
+                    bytes_available,
+                });
+            }
+            Err(_) => return None,
+        }
+
+        let body_start = file.stream_position().ok()?;
+        let (mut chunks_end, uncompressed_length) = zlib_reader::chunk_extent(file, end).ok()?;
+        if chunks_end == body_start {
+            // No chunks at all. The first chunk header is needed to read the body length.
+            chunks_end += zlib_reader::CHUNK_HEADER_LENGTH;
+        }
+        if chunks_end > end {
+            return Some(ParseError::Truncated {
+                stage: TruncatedStage::Chunk,
+                bytes_expected: chunks_end - start,
+                bytes_available,
+            });
+        }
+
+        file.seek(SeekFrom::Start(body_start)).ok()?;
+        let body_length = ChunkedZLibReader::new(file).ok()?.body_length();
+        let bytes_available = uncompressed_length.saturating_sub(4);
+        if body_length > bytes_available {
+            return Some(ParseError::Truncated {
+                stage: TruncatedStage::Objects,
+                bytes_expected: body_length,
+                bytes_available,
+            });
+        }
+        None
+    }
+
+    /// Same as `parse()` but also returns statistics about the compressed body.
+    pub fn parse_with_stats<R>(file: &mut R) -> Result<(SaveFile, ParseStats)>
+    where
+        R: Read + Seek,
+    {
+        let (result, stats) = SaveFile::read_file_checked(file, &ParseOptions::default(), false)?;
+        Ok((result.save_file, stats))
+    }
+
+    /// Same as `parse()` but an error in the compressed body does not discard the objects that were
+    /// read before it. Errors in the header are still returned as `Err`. If parsing stopped early,
+    /// `object_data` may be shorter than `save_objects`. Unexpected data at the end of the body is
+    /// ignored.
+    pub fn parse_lenient<R>(file: &mut R) -> Result<LenientParse>
+    where
+        R: Read + Seek,
+    {
+        let (result, _) = SaveFile::read_file_checked(file, &ParseOptions::default(), true)?;
+        Ok(result)
+    }
+
+    /// Reads the header and returns the decompressed body without the body length prefix. Offsets
+    /// into the returned buffer are the body positions reported by `ParseError::At`.
+    pub fn decompress_body<R>(file: &mut R) -> Result<Vec<u8>>
+    where
+        R: Read,
+    {
+        SaveFile::parse_header(file)?;
+        let mut decoder = ChunkedZLibReader::new(file)?;
+        let body_length = decoder.body_length();
+        let mut body = Vec::new();
+        decoder.read_to_end(&mut body)?;
+        if body.len() as u64 != body_length {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(body)
+    }
+
+    /// Reads only the uncompressed header. This is much faster than `parse()` when only the session
+    /// name, play time, or date is needed, because the body is not decompressed.
+    pub fn peek_header<R>(file: &mut R) -> Result<SaveHeader>
+    where
+        R: Read,
+    {
+        let mut file = PositionTracking::new(file);
+        SaveFile::read_header(&mut file).map_err(|source| ParseError::At {
+            section: Section::Header,
+            position: file.position(),
+            source: Box::new(source),
+        })
+    }
+
+    fn parse_header<R>(file: &mut R) -> Result<SaveFile>
+    where
+        R: Read,
+    {
+        let mut file = PositionTracking::new(file);
+        let header = SaveFile::peek_header(&mut file)?;
+        Ok(SaveFile {
+            header_byte_len: file.position(),
+            ..header.into()
+        })
+    }
+
+    fn read_header<R>(file: &mut R) -> Result<SaveHeader>
+    where
+        R: Read,
     {
         // https://github.com/Goz3rr/SatisfactorySaveEditor
         // https://satisfactory.fandom.com/wiki/Save_files (outdated info)
 
-        let mut save_file = SaveFile {
-            save_header: file.read_i32::<L>()?,
+        let save_header = file.read_i32::<L>()?;
+        if save_header != SaveFile::SAVE_HEADER_VERSION {
+            return Err(ParseError::UnsupportedSaveHeader(save_header));
+        }
+
+        Ok(SaveHeader {
+            save_header,
             save_version: file.read_i32::<L>()?,
             build_version: file.read_i32::<L>()?,
             world_type: read_string(file)?,
             world_properties: WorldProperties::parse(&read_string(file)?)?,
             session_name: read_string(file)?,
-            play_time: Duration::seconds(file.read_i32::<L>()?.try_into()?),
+            play_time: Duration::seconds(file.read_i32::<L>()?.into()),
             save_date: SaveFile::convert_date(file.read_i64::<L>()?),
             session_visibility: SessionVisiblity::from_u8(file.read_u8()?)?,
             editor_object_version: file.read_i32::<L>()?,
             mod_meta_data: read_string(file)?,
             is_modded_save: file.read_i32::<L>()? > 0,
-            save_objects: Vec::new(),
-        };
+        })
+    }
+
+    /// Returns a copy of the header fields.
+    pub fn header(&self) -> SaveHeader {
+        SaveHeader {
+            save_header: self.save_header,
+            save_version: self.save_version,
+            build_version: self.build_version,
+            world_type: self.world_type.clone(),
+            world_properties: self.world_properties.clone(),
+            session_name: self.session_name.clone(),
+            play_time: self.play_time,
+            save_date: self.save_date,
+            session_visibility: self.session_visibility,
+            editor_object_version: self.editor_object_version,
+            mod_meta_data: self.mod_meta_data.clone(),
+            is_modded_save: self.is_modded_save,
+        }
+    }
+
+    /// Reads the objects, their data, and the collected objects from the decompressed body. Values
+    /// are pushed as they are read so that they are kept if an error occurs.
+    fn parse_objects<R>(
+        decoder: &mut R,
+        body_length: u64,
+        save_file: &mut SaveFile,
+        options: &ParseOptions,
+    ) -> Result<()>
+    where
+        R: Read,
+    {
+        let mut decoder = PositionTracking::new(decoder);
+        SaveFile::read_objects(&mut decoder, body_length, save_file, options).map_err(|source| {
+            ParseError::At {
+                section: Section::Body,
+                position: decoder.position(),
+                source: Box::new(source),
+            }
+        })
+    }
+
+    /// `body_length` bounds the counts that are read so that a corrupt count fails before the
+    /// loop or an allocation depends on it.
+    fn read_objects<R>(
+        decoder: &mut PositionTracking<R>,
+        body_length: u64,
+        save_file: &mut SaveFile,
+        options: &ParseOptions,
+    ) -> Result<()>
+    where
+        R: Read,
+    {
+        // Minimum encoded sizes: a component header with empty strings, a data length prefix, and
+        // a reference with empty strings
+        const MIN_OBJECT_SIZE: u64 = 4 + 4 * 4;
+        const MIN_OBJECT_DATA_SIZE: u64 = 4;
+        const MIN_REFERENCE_SIZE: u64 = 2 * 4;
 
-        let mut decoder = ChunkedZLibReader::new(file)?;
         let world_object_count = decoder.read_u32::<L>()?;
-        save_file.save_objects.reserve(world_object_count as usize);
-        for _ in 0..world_object_count {
+        check_count(world_object_count, MIN_OBJECT_SIZE, decoder, body_length)?;
+        if options.type_path_filter.is_none() {
+            save_file.save_objects.reserve(world_object_count as usize);
+        }
+        let mut included: Vec<bool> = Vec::new();
+        #[cfg(feature = "trace")]
+        let mut parse_times = parse_times::ParseTimes::default();
+        for index in 0..world_object_count {
+            #[cfg(feature = "trace")]
+            let start = std::time::Instant::now();
+            let offset = decoder.position();
+            let object = SaveObject::parse_with_options(decoder, options).map_err(|e| match e {
+                // The size of an unknown object cannot be known, so parsing cannot continue after it
+                ParseError::UnknownObjectType(object_type) => ParseError::UnknownObject {
+                    object_type,
+                    index,
+                    offset,
+                },
+                e => e,
+            })?;
+            #[cfg(feature = "trace")]
+            parse_times.add(object.type_path(), start.elapsed());
+            let include = options.includes(&object);
+            if include {
+                save_file.save_objects.push(object);
+            }
+            included.push(include);
+        }
+        #[cfg(feature = "trace")]
+        parse_times.log();
+
+        let object_data_count = decoder.read_u32::<L>()?;
+        if object_data_count != world_object_count {
+            return Err(ParseError::ObjectDataCountMismatch {
+                object_data_count,
+                object_count: world_object_count,
+            });
+        }
+        check_count(
+            object_data_count,
+            MIN_OBJECT_DATA_SIZE,
+            decoder,
+            body_length,
+        )?;
+        save_file.object_data.reserve(save_file.save_objects.len());
+        for include in included {
+            let data = read_object_data(decoder, body_length)?;
+            if include {
+                save_file.object_data.push(data);
+            }
+        }
+
+        let collected_object_count = decoder.read_u32::<L>()?;
+        check_count(
+            collected_object_count,
+            MIN_REFERENCE_SIZE,
+            decoder,
+            body_length,
+        )?;
+        for _ in 0..collected_object_count {
             save_file
-                .save_objects
-                .push(SaveObject::parse(&mut decoder)?);
+                .collected_objects
+                .push(ObjectReference::parse(decoder)?);
+        }
+        Ok(())
+    }
+
+    fn set_body_sizes<R>(&mut self, decoder: &ChunkedZLibReader<R>)
+    where
+        R: Read,
+    {
+        self.compressed_size = decoder.compressed_size();
+        self.decompressed_size = decoder.decompressed_size();
+    }
+
+    /// Returns `ParseError::TrailingData` if the body has not been fully read.
+    fn check_trailing_data<R>(decoder: &ChunkedZLibReader<R>) -> Result<()>
+    where
+        R: Read,
+    {
+        match decoder.remaining_body_length() {
+            0 => Ok(()),
+            remaining => Err(ParseError::TrailingData { remaining }),
+        }
+    }
+
+    /// Writes the save file in the format that `parse()` reads. Returns an error if `object_data`
+    /// does not have one entry for each object. Use `save_writer::SaveWriter` to write objects
+    /// without holding them all in memory.
+    pub fn write<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        self.write_with_options(file, &WriteOptions::default())
+    }
+
+    /// Same as `write()` but with options that control how the file is written.
+    pub fn write_with_options<W>(&self, file: &mut W, options: &WriteOptions) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let mut writer = save_writer::SaveWriter::with_level(file, options.compression);
+        writer.write_header(self)?;
+        for object in &self.save_objects {
+            writer.write_object(object)?;
+        }
+        for data in &self.object_data {
+            writer.write_object_data(data)?;
+        }
+        writer.finish(&self.collected_objects)?;
+        Ok(())
+    }
+
+    /// Renames the object whose `instance_name` is `old` to `new`, and updates the references to it
+    /// in components' `parent_entity_name` and in `collected_objects`. References inside
+    /// `object_data` are not decoded and are left unchanged.
+    ///
+    /// Returns an error if no object is named `old` or another object is already named `new`.
+    /// Renaming an object to its own name does nothing.
+    pub fn rename_object(&mut self, old: &str, new: &str) -> Result<()> {
+        let index = self
+            .save_objects
+            .iter()
+            .position(|object| object.instance_name() == old)
+            .ok_or_else(|| ParseError::ObjectNotFound(old.to_string()))?;
+        if old == new {
+            return Ok(());
+        }
+        if self
+            .save_objects
+            .iter()
+            .any(|object| object.instance_name() == new)
+        {
+            return Err(ParseError::ObjectExists(new.to_string()));
+        }
+        *self.save_objects[index].instance_name_mut() = new.to_string();
+
+        for object in &mut self.save_objects {
+            if let SaveObject::SaveComponent {
+                parent_entity_name, ..
+            } = object
+            {
+                if parent_entity_name == old {
+                    *parent_entity_name = new.to_string();
+                }
+            }
+        }
+        for reference in &mut self.collected_objects {
+            if reference.path_name == old {
+                reference.path_name = new.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a Graphviz DOT graph with a node for each object and an edge from each component to
+    /// its parent entity. Entities are blue and components are gray. References inside
+    /// `object_data` are not decoded, so they are not included.
+    pub fn to_dot(&self) -> String {
+        let mut result = "digraph save {\n".to_string();
+        for object in &self.save_objects {
+            let color = match object {
+                SaveObject::SaveEntity { .. } => "lightblue",
+                SaveObject::SaveComponent { .. } => "lightgray",
+            };
+            result.push_str(&format!(
+                "    {} [style=filled, fillcolor={}];\n",
+                dot_id(object.instance_name()),
+                color
+            ));
+        }
+        for (source, reference) in self.object_references() {
+            result.push_str(&format!(
+                "    {} -> {};\n",
+                dot_id(&source.0),
+                dot_id(&reference.path_name)
+            ));
+        }
+        result.push_str("}\n");
+        result
+    }
+
+    /// Returns every reference from one object to another, paired with the referencing object.
+    ///
+    /// Object data is not decoded, so this only covers references in object headers, which are
+    /// the parent entities of components. The parent is assumed to be in the component's level.
+    pub fn object_references(&self) -> Vec<(ObjectId, ObjectReference)> {
+        self.components()
+            .filter(|component| !component.parent_entity_name().is_empty())
+            .map(|component| {
+                let reference = ObjectReference {
+                    level_name: component.root_object().to_string(),
+                    path_name: component.parent_entity_name().to_string(),
+                };
+                (ObjectId(component.instance_name().to_string()), reference)
+            })
+            .collect()
+    }
+
+    /// Returns `decompressed_size / compressed_size`, or 0 if the body was not parsed.
+    pub fn compression_ratio(&self) -> f32 {
+        if self.compressed_size == 0 {
+            0.0
+        } else {
+            self.decompressed_size as f32 / self.compressed_size as f32
+        }
+    }
+
+    /// Returns `build_version` as a `BuildVersion`.
+    pub fn build_version(&self) -> BuildVersion {
+        BuildVersion(self.build_version)
+    }
+
+    /// Returns `mod_meta_data` only if `is_modded_save` is set.
+    pub fn mod_status(&self) -> ModStatus<'_> {
+        if self.is_modded_save {
+            ModStatus::Modded(&self.mod_meta_data)
+        } else {
+            ModStatus::Unmodded
+        }
+    }
+
+    /// Sets both `is_modded_save` and `mod_meta_data`. `ModStatus::Unmodded` clears the metadata.
+    pub fn set_mod_status(&mut self, mod_status: ModStatus<'_>) {
+        match mod_status {
+            ModStatus::Unmodded => {
+                self.is_modded_save = false;
+                self.mod_meta_data.clear();
+            }
+            ModStatus::Modded(mod_meta_data) => {
+                self.is_modded_save = true;
+                self.mod_meta_data = mod_meta_data.to_string();
+            }
+        }
+    }
+
+    /// Sets both `session_name` and `world_properties.session_name`.
+    pub fn set_session_name(&mut self, session_name: &str) {
+        self.session_name = session_name.to_string();
+        self.world_properties.session_name = session_name.to_string();
+    }
+
+    /// Sets both `session_visibility` and `world_properties.visibility`.
+    pub fn set_visibility(&mut self, visibility: SessionVisiblity) {
+        self.session_visibility = visibility;
+        self.world_properties.visibility = visibility;
+    }
+
+    /// Sets `play_time`, truncating it to whole seconds because that is all the header stores.
+    /// Returns an error if the number of seconds does not fit in an i32.
+    pub fn set_play_time(&mut self, play_time: Duration) -> Result<()> {
+        let seconds: i32 = play_time
+            .num_seconds()
+            .try_into()
+            .map_err(|_| ParseError::InvalidPlayTime(play_time))?;
+        self.play_time = Duration::seconds(seconds.into());
+        Ok(())
+    }
+
+    /// Returns how much play time advanced since `earlier`. The result is negative if `earlier`
+    /// has more play time.
+    pub fn play_time_since(&self, earlier: &SaveFile) -> Duration {
+        self.play_time - earlier.play_time
+    }
+
+    /// Returns the time between the save dates of `earlier` and this save. The result is negative
+    /// if `earlier` was saved later.
+    pub fn real_time_since(&self, earlier: &SaveFile) -> Duration {
+        self.save_date.signed_duration_since(earlier.save_date)
+    }
+
+    /// Returns `save_date` in the local time zone of this computer.
+    pub fn save_date_local(&self) -> DateTime<chrono::Local> {
+        self.save_date_in(&chrono::Local)
+    }
+
+    /// Returns `save_date` in the time zone `tz`.
+    pub fn save_date_in<Tz>(&self, tz: &Tz) -> DateTime<Tz>
+    where
+        Tz: TimeZone,
+    {
+        self.save_date.with_timezone(tz)
+    }
+
+    /// Returns the object that `reference` points to. Both the level and the path name must match,
+    /// because the same path name can be used in different levels. This scans `save_objects`.
+    pub fn resolve(&self, reference: &ObjectReference) -> Option<&SaveObject> {
+        self.save_objects.iter().find(|object| {
+            object.instance_name() == reference.path_name
+                && object.root_object() == reference.level_name
+        })
+    }
+
+    /// Returns the objects whose `type_path` contains `suffix`, ignoring ASCII case. For example,
+    /// "constructor" matches `.../Build_ConstructorMk1.Build_ConstructorMk1_C`.
+    ///
+    /// This is a substring match on the type path, not a query of the class hierarchy, so it also
+    /// matches unrelated classes with similar names.
+    pub fn find_objects_by_class_suffix(&self, suffix: &str) -> Vec<&SaveObject> {
+        let suffix = suffix.to_ascii_lowercase();
+        self.save_objects
+            .iter()
+            .filter(|object| object.type_path().to_ascii_lowercase().contains(&suffix))
+            .collect()
+    }
+
+    /// Returns the entities whose position is within `radius` of `center`, including those exactly
+    /// `radius` away. Components have no position and are never returned.
+    pub fn objects_in_radius(&self, center: Vector3, radius: f32) -> Vec<&SaveObject> {
+        self.save_objects
+            .iter()
+            .filter(|object| {
+                object
+                    .as_entity()
+                    .is_some_and(|entity| entity.position().distance(&center) <= radius)
+            })
+            .collect()
+    }
+
+    /// Consumes the save file and returns its objects without cloning them.
+    pub fn into_objects(self) -> Vec<SaveObject> {
+        self.save_objects
+    }
+
+    /// Returns the objects for editing in place. If objects are added or removed, `object_data`
+    /// must be updated to match.
+    pub fn objects_mut(&mut self) -> &mut Vec<SaveObject> {
+        &mut self.save_objects
+    }
+
+    /// Returns the declared data length of the object at `index` in `save_objects`, or None if it
+    /// has no data, which happens after `parse_lenient()` stopped early.
+    pub fn object_data_len(&self, index: usize) -> Option<usize> {
+        self.object_data.get(index).map(Vec::len)
+    }
+
+    /// Returns a copy of the save file that only contains the objects for which `predicate`
+    /// returns true, along with their data. `compressed_size`, `decompressed_size`, and
+    /// `header_byte_len` are 0 because the copy was not parsed. The other fields are copied
+    /// unchanged.
+    pub fn filter_objects<F>(&self, predicate: F) -> SaveFile
+    where
+        F: Fn(&SaveObject) -> bool,
+    {
+        let mut save_objects = Vec::new();
+        let mut object_data = Vec::new();
+        for (i, object) in self.save_objects.iter().enumerate() {
+            if predicate(object) {
+                save_objects.push(object.clone());
+                // object_data may be shorter after a lenient parse
+                if let Some(data) = self.object_data.get(i) {
+                    object_data.push(data.clone());
+                }
+            }
+        }
+
+        SaveFile {
+            save_header: self.save_header,
+            save_version: self.save_version,
+            build_version: self.build_version,
+            world_type: self.world_type.clone(),
+            world_properties: self.world_properties.clone(),
+            session_name: self.session_name.clone(),
+            play_time: self.play_time,
+            save_date: self.save_date,
+            session_visibility: self.session_visibility,
+            editor_object_version: self.editor_object_version,
+            mod_meta_data: self.mod_meta_data.clone(),
+            is_modded_save: self.is_modded_save,
+            save_objects,
+            object_data,
+            collected_objects: self.collected_objects.clone(),
+            compressed_size: 0,
+            decompressed_size: 0,
+            header_byte_len: 0,
+        }
+    }
+
+    /// Compares everything except `play_time` and `save_date`, which change every time the same
+    /// world is saved, and `compressed_size`, `decompressed_size`, and `header_byte_len`, which
+    /// depend on how the file was encoded.
+    pub fn structurally_eq(&self, other: &SaveFile) -> bool {
+        // Destructured so that new fields are not forgotten here
+        let SaveFile {
+            save_header,
+            save_version,
+            build_version,
+            world_type,
+            world_properties,
+            session_name,
+            play_time: _,
+            save_date: _,
+            session_visibility,
+            editor_object_version,
+            mod_meta_data,
+            is_modded_save,
+            save_objects,
+            object_data,
+            collected_objects,
+            compressed_size: _,
+            decompressed_size: _,
+            header_byte_len: _,
+        } = self;
+
+        *save_header == other.save_header
+            && *save_version == other.save_version
+            && *build_version == other.build_version
+            && *world_type == other.world_type
+            && *world_properties == other.world_properties
+            && *session_name == other.session_name
+            && *session_visibility == other.session_visibility
+            && *editor_object_version == other.editor_object_version
+            && *mod_meta_data == other.mod_meta_data
+            && *is_modded_save == other.is_modded_save
+            && *save_objects == other.save_objects
+            && *object_data == other.object_data
+            && *collected_objects == other.collected_objects
+    }
+
+    /// Returns the number of entities and components in `save_objects`.
+    pub fn object_count_by_variant(&self) -> (usize, usize) {
+        self.save_objects
+            .iter()
+            .fold((0, 0), |(entities, components), object| match object {
+                SaveObject::SaveEntity { .. } => (entities + 1, components),
+                SaveObject::SaveComponent { .. } => (entities, components + 1),
+            })
+    }
+
+    /// Counts objects by the root namespace of their `type_path`. For `/Script/` and `/Game/` paths
+    /// this is the first two segments, such as `/Script/FactoryGame` or `/Game/FactoryGame`, and for
+    /// other paths, which are usually mod content, it is the first segment.
+    pub fn statistics_by_prefix(&self) -> HashMap<String, usize> {
+        let mut result: HashMap<String, usize> = HashMap::new();
+        for object in &self.save_objects {
+            *result
+                .entry(type_path_prefix(object.type_path()).to_string())
+                .or_default() += 1;
+        }
+        result
+    }
+
+    /// Iterates over the entities in `save_objects`.
+    pub fn entities(&self) -> impl Iterator<Item = SaveEntityView<'_>> {
+        self.save_objects.iter().filter_map(SaveObject::as_entity)
+    }
+
+    /// Iterates over the entities in `save_objects` for editing their transforms.
+    pub fn entities_mut(&mut self) -> impl Iterator<Item = SaveEntityViewMut<'_>> {
+        self.save_objects
+            .iter_mut()
+            .filter_map(SaveObject::as_entity_mut)
+    }
+
+    /// Moves every entity by `offset`. Rotations and scales are unchanged. Components have no
+    /// position and are unchanged.
+    ///
+    /// Positions inside `object_data`, such as spline points of belts and pipes, are not decoded and
+    /// are not moved, so those objects will look detached after a large offset.
+    pub fn translate_all(&mut self, offset: Vector3) {
+        for mut entity in self.entities_mut() {
+            let position = entity.position_mut();
+            *position = *position + offset;
         }
-        Ok(save_file)
+    }
+
+    /// Iterates over the components in `save_objects`.
+    pub fn components(&self) -> impl Iterator<Item = SaveComponentView<'_>> {
+        self.save_objects
+            .iter()
+            .filter_map(SaveObject::as_component)
     }
 
     fn zero_date() -> DateTime<Utc> {
-        chrono::Utc.ymd(1, 1, 1).and_hms(12, 0, 0)
+        chrono::Utc.with_ymd_and_hms(1, 1, 1, 12, 0, 0).unwrap()
     }
 
     fn convert_date(n: i64) -> DateTime<Utc> {
         SaveFile::zero_date() + Duration::nanoseconds(n) * 100
     }
+
+    /// Inverse of `convert_date()`. Returns `None` if the date is out of range.
+    fn date_to_ticks(date: DateTime<Utc>) -> Option<i64> {
+        let duration = date - SaveFile::zero_date();
+        // num_nanoseconds() overflows after 292 years, so seconds and nanoseconds are separate
+        duration
+            .num_seconds()
+            .checked_mul(10_000_000)?
+            .checked_add(i64::from(duration.subsec_nanos() / 100))
+    }
 }
 
 impl Default for SaveFile {
@@ -98,47 +909,525 @@ impl Default for SaveFile {
             mod_meta_data: Default::default(),
             is_modded_save: Default::default(),
             save_objects: Default::default(),
+            object_data: Default::default(),
+            collected_objects: Default::default(),
+            compressed_size: Default::default(),
+            decompressed_size: Default::default(),
+            header_byte_len: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
-pub struct WorldProperties {
-    pub start_loc: String,
-    pub session_name: String,
-    pub visibility: SessionVisiblity,
+/// Iterates over `save_objects`.
+impl<'a> IntoIterator for &'a SaveFile {
+    type Item = &'a SaveObject;
+    type IntoIter = std::slice::Iter<'a, SaveObject>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.save_objects.iter()
+    }
 }
 
-impl WorldProperties {
-    pub fn parse(s: &str) -> Result<WorldProperties> {
-        let mut map: HashMap<&str, &str> = s
-            .split('?')
-            .skip(1) // Nothing before first "?"
-            .map(|s| {
-                s.split_once("=")
-                    .ok_or_else(|| Error::msg(format!("invalid property: {}", s)))
-            })
-            .collect::<Result<HashMap<&str, &str>>>()?;
+/// Iterates over `save_objects`.
+impl<'a> IntoIterator for &'a mut SaveFile {
+    type Item = &'a mut SaveObject;
+    type IntoIter = std::slice::IterMut<'a, SaveObject>;
 
-        let not_found_error = || Error::msg("property not found");
-        Ok(WorldProperties {
-            start_loc: map
-                .remove("startloc")
-                .ok_or_else(not_found_error)?
-                .to_string(),
+    fn into_iter(self) -> Self::IntoIter {
+        self.save_objects.iter_mut()
+    }
+}
+
+/// Uncompressed header fields of a save file, returned by `SaveFile::peek_header()`. The fields
+/// are the same as in `SaveFile`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveHeader {
+    pub save_header: i32,
+    pub save_version: i32,
+    pub build_version: i32,
+    pub world_type: String,
+    pub world_properties: WorldProperties,
+    pub session_name: String,
+    pub play_time: Duration,
+    pub save_date: DateTime<Utc>,
+    pub session_visibility: SessionVisiblity,
+    pub editor_object_version: i32,
+    pub mod_meta_data: String,
+    pub is_modded_save: bool,
+}
+
+/// Range of versions that has been tested, returned by `SaveFile::supported_versions()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupportedVersions {
+    pub save_header: i32,
+    pub save_versions: std::ops::RangeInclusive<i32>,
+    pub build_versions: std::ops::RangeInclusive<BuildVersion>,
+}
+
+/// Creates a save file with no objects.
+impl From<SaveHeader> for SaveFile {
+    fn from(header: SaveHeader) -> Self {
+        SaveFile {
+            save_header: header.save_header,
+            save_version: header.save_version,
+            build_version: header.build_version,
+            world_type: header.world_type,
+            world_properties: header.world_properties,
+            session_name: header.session_name,
+            play_time: header.play_time,
+            save_date: header.save_date,
+            session_visibility: header.session_visibility,
+            editor_object_version: header.editor_object_version,
+            mod_meta_data: header.mod_meta_data,
+            is_modded_save: header.is_modded_save,
+            ..Default::default()
+        }
+    }
+}
+
+/// Result type of the functions in this crate.
+pub type Result<T, E = ParseError> = std::result::Result<T, E>;
+
+/// Error returned by the functions in this crate.
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    /// The underlying reader or writer failed. The `std::io::Error` is also returned by
+    /// `source()`.
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[error("unsupported save header version: {0}")]
+    UnsupportedSaveHeader(i32),
+    #[error("invalid body length: {0}")]
+    InvalidBodyLength(i32),
+    #[error("invalid length in chunk header: {0}")]
+    InvalidChunkLength(i64),
+    #[error("chunk length {length} exceeds max chunk size {max_chunk_size}")]
+    ChunkTooLarge { length: u64, max_chunk_size: u64 },
+    #[error("invalid string length: {0}")]
+    InvalidStringLength(i32),
+    #[error("invalid property: {0}")]
+    InvalidProperty(String),
+    #[error("property not found: {0}")]
+    PropertyNotFound(&'static str),
+    #[error("invalid session visibility: {0}")]
+    InvalidVisibility(String),
+    #[error("invalid session visibility value: {0}")]
+    InvalidVisibilityValue(u8),
+    #[error("play time out of range: {0}")]
+    InvalidPlayTime(Duration),
+    #[error("unknown object type: {0}")]
+    UnknownObjectType(i32),
+    /// Object number `index` of the object list, starting at `offset` in the body, has an unknown
+    /// type. The objects before it have been read.
+    #[error("unknown object type {object_type} in object {index} at byte {offset}")]
+    UnknownObject {
+        object_type: i32,
+        index: u32,
+        offset: u64,
+    },
+    #[error("non-finite transform in {0}")]
+    NonFiniteTransform(String),
+    #[error("invalid bool: {0}")]
+    InvalidBool(i32),
+    #[error("invalid object data length: {0}")]
+    InvalidObjectDataLength(i32),
+    #[error("object data count {object_data_count} does not match object count {object_count}")]
+    ObjectDataCountMismatch {
+        object_data_count: u32,
+        object_count: u32,
+    },
+    #[error("count {count} does not fit in the remaining {remaining} bytes of the body")]
+    CountExceedsBody { count: u32, remaining: u64 },
+    #[error("data length {length} exceeds the remaining {remaining} bytes of the body")]
+    DataLengthExceedsBody { length: u64, remaining: u64 },
+    #[error("object already exists: {0}")]
+    ObjectExists(String),
+    #[error("object not found: {0}")]
+    ObjectNotFound(String),
+    /// `source` occurred after `position` bytes of `section` were read.
+    #[error("error in {section} at byte {position}")]
+    At {
+        section: Section,
+        position: u64,
+        source: Box<ParseError>,
+    },
+    /// The body has `remaining` bytes left after the last section was read.
+    #[error("{remaining} bytes of unexpected data at end of body")]
+    TrailingData { remaining: u64 },
+    /// A read of `needed` bytes went past the end of the input, which had `remaining` bytes left.
+    /// Returned by `SliceReader::parse()`.
+    #[error("unexpected end of input: needed {needed} bytes but {remaining} remain")]
+    UnexpectedEof { needed: usize, remaining: usize },
+    /// The file is shorter than its contents declare, usually because it was not completely
+    /// copied or downloaded. See `TruncatedStage` for what the byte counts mean.
+    #[error(
+        "save file is incomplete: {stage} needs {bytes_expected} bytes but only {bytes_available} \
+         are available"
+    )]
+    Truncated {
+        stage: TruncatedStage,
+        bytes_expected: u64,
+        bytes_available: u64,
+    },
+}
+
+/// Where a truncated file ends, returned in `ParseError::Truncated`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TruncatedStage {
+    /// The file ends in the header. Byte counts are file lengths, and `bytes_expected` is only a
+    /// lower bound because the header length depends on its strings.
+    Header,
+    /// The file ends in a compressed chunk or chunk header. Byte counts are file lengths.
+    Chunk,
+    /// Every chunk is complete but chunks are missing, so there are fewer decompressed bytes than
+    /// the body length. Byte counts are decompressed body lengths, not counting the body length
+    /// prefix.
+    Objects,
+}
+
+impl fmt::Display for TruncatedStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TruncatedStage::Header => "header",
+            TruncatedStage::Chunk => "compressed chunk",
+            TruncatedStage::Objects => "body",
+        })
+    }
+}
+
+/// Section of the save file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Section {
+    /// The uncompressed header at the start of the file. Positions are offsets from the start of
+    /// the file.
+    Header,
+    /// The decompressed body. Positions are offsets from the start of the object list, after the
+    /// body length prefix.
+    Body,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Section::Header => "header",
+            Section::Body => "body",
+        })
+    }
+}
+
+/// Options for `SaveFile::parse_with_options()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParseOptions {
+    /// If set, only objects whose `type_path` is in this set are kept. The other objects still
+    /// have to be read to find the start of the next object.
+    pub type_path_filter: Option<HashSet<String>>,
+    /// If true, an entity whose transform contains NaN or infinity is an error. Such values are
+    /// usually a sign of corruption.
+    pub reject_non_finite: bool,
+}
+
+/// Options for `SaveFile::write_with_options()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct WriteOptions {
+    /// zlib compression level of the body. The default is the level that the game uses.
+    pub compression: Compression,
+}
+
+impl ParseOptions {
+    fn includes(&self, object: &SaveObject) -> bool {
+        match &self.type_path_filter {
+            Some(filter) => filter.contains(object.type_path()),
+            None => true,
+        }
+    }
+}
+
+/// Statistics about the compressed body, returned by `SaveFile::parse_with_stats()`.
+///
+/// Chunks are read lazily, so these only cover the chunks that the parser needed.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ParseStats {
+    /// Total compressed length of the chunks that were read.
+    pub compressed_bytes: u64,
+    /// Number of bytes decompressed and consumed by the parser.
+    pub decompressed_bytes: u64,
+    pub chunk_count: usize,
+    /// 0 if no chunks were read.
+    pub objects_per_chunk: f64,
+}
+
+impl ParseStats {
+    fn new<R>(decoder: &ChunkedZLibReader<R>, object_count: usize) -> Self
+    where
+        R: Read,
+    {
+        let chunk_count = decoder.chunk_count();
+        Self {
+            compressed_bytes: decoder.compressed_size(),
+            decompressed_bytes: decoder.decompressed_size(),
+            chunk_count,
+            objects_per_chunk: if chunk_count == 0 {
+                0.0
+            } else {
+                object_count as f64 / chunk_count as f64
+            },
+        }
+    }
+}
+
+/// Game build number that a save file was written by.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BuildVersion(pub i32);
+
+impl BuildVersion {
+    /// Oldest build that this crate has been tested with.
+    pub const OLDEST_TESTED: BuildVersion = BuildVersion(152331);
+    /// Newest build that this crate has been tested with.
+    pub const NEWEST_TESTED: BuildVersion = BuildVersion(155350);
+
+    pub fn is_at_least(&self, other: BuildVersion) -> bool {
+        *self >= other
+    }
+
+    /// Returns true if this build is within the range of tested builds.
+    pub fn is_tested(&self) -> bool {
+        (BuildVersion::OLDEST_TESTED..=BuildVersion::NEWEST_TESTED).contains(self)
+    }
+}
+
+impl From<i32> for BuildVersion {
+    fn from(n: i32) -> Self {
+        Self(n)
+    }
+}
+
+impl fmt::Display for BuildVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Whether a save was made with mods, returned by `SaveFile::mod_status()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ModStatus<'a> {
+    Unmodded,
+    /// Holds the mod metadata that the mod loader wrote. It is JSON but is not parsed here.
+    Modded(&'a str),
+}
+
+/// Whether a save was made by the player or by the game.
+///
+/// Header version 8 does not record this, so it is only available from the file name.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SaveType {
+    Manual,
+    /// Named `<session name>_autosave_<n>.sav` by the game.
+    Auto,
+}
+
+impl SaveType {
+    /// Classifies a save by its file name. `filename` may include a directory and the `.sav`
+    /// extension. Names that don't follow the autosave pattern are `Manual`.
+    pub fn from_filename(filename: &str) -> SaveType {
+        let name = filename.rsplit(['/', '\\']).next().unwrap_or("");
+        let stem = match name.len().checked_sub(4) {
+            Some(i) if name.is_char_boundary(i) && name[i..].eq_ignore_ascii_case(".sav") => {
+                &name[..i]
+            }
+            _ => name,
+        };
+
+        let is_autosave = stem.rsplit_once("_autosave_").is_some_and(|(session, n)| {
+            !session.is_empty() && !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())
+        });
+        if is_autosave {
+            SaveType::Auto
+        } else {
+            SaveType::Manual
+        }
+    }
+}
+
+/// Result of `SaveFile::parse_lenient()`.
+#[derive(Debug)]
+pub struct LenientParse {
+    /// Contains the objects that were read before `error` occurred.
+    pub save_file: SaveFile,
+    /// True if an error stopped parsing before all objects were read.
+    pub partial: bool,
+    pub error: Option<ParseError>,
+}
+
+/// Builds a `SaveFile` programmatically. Unset fields match `SaveFile::default()`, except that the
+/// versions are ones that `SaveFile::parse()` can read back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveFileBuilder {
+    save_file: SaveFile,
+}
+
+impl SaveFileBuilder {
+    pub fn new() -> Self {
+        Self {
+            save_file: SaveFile {
+                save_header: SaveFile::SAVE_HEADER_VERSION,
+                save_version: SaveFile::TESTED_SAVE_VERSION,
+                build_version: BuildVersion::NEWEST_TESTED.0,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Sets both `session_name` and `world_properties.session_name`.
+    pub fn session_name(mut self, session_name: &str) -> Self {
+        self.save_file.set_session_name(session_name);
+        self
+    }
+
+    pub fn save_version(mut self, save_version: i32) -> Self {
+        self.save_file.save_version = save_version;
+        self
+    }
+
+    pub fn build_version(mut self, build_version: i32) -> Self {
+        self.save_file.build_version = build_version;
+        self
+    }
+
+    pub fn world_type(mut self, world_type: &str) -> Self {
+        self.save_file.world_type = world_type.to_string();
+        self
+    }
+
+    /// Sets both `session_visibility` and `world_properties.visibility`.
+    pub fn visibility(mut self, visibility: SessionVisiblity) -> Self {
+        self.save_file.set_visibility(visibility);
+        self
+    }
+
+    /// Adds an object with empty data.
+    pub fn add_object(mut self, object: SaveObject) -> Self {
+        self.save_file.save_objects.push(object);
+        self.save_file.object_data.push(Vec::new());
+        self
+    }
+
+    pub fn build(self) -> SaveFile {
+        self.save_file
+    }
+}
+
+impl Default for SaveFileBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WorldProperties {
+    pub start_loc: String,
+    pub session_name: String,
+    pub visibility: SessionVisiblity,
+}
+
+impl WorldProperties {
+    /// Parses a query string such as `?startloc=Grass Fields?sessionName=test?Visibility=SV_Private`.
+    /// Keys are matched case-insensitively after trimming whitespace, and percent-encoded values
+    /// are decoded.
+    pub fn parse(s: &str) -> Result<WorldProperties> {
+        let mut map: HashMap<String, String> = s
+            .split('?')
+            .skip(1) // Nothing before first "?"
+            .map(|s| {
+                s.split_once("=")
+                    .map(|(key, value)| (key.trim().to_lowercase(), percent_decode(value)))
+                    .ok_or_else(|| ParseError::InvalidProperty(s.to_string()))
+            })
+            .collect::<Result<HashMap<String, String>>>()?;
+
+        Ok(WorldProperties {
+            start_loc: map
+                .remove("startloc")
+                .ok_or(ParseError::PropertyNotFound("startloc"))?,
             session_name: map
-                .remove("sessionName")
-                .ok_or_else(not_found_error)?
-                .to_string(),
+                .remove("sessionname")
+                .ok_or(ParseError::PropertyNotFound("sessionName"))?,
             visibility: SessionVisiblity::parse(
-                map.remove("Visibility").ok_or_else(not_found_error)?,
+                map.remove("visibility")
+                    .ok_or(ParseError::PropertyNotFound("Visibility"))?
+                    .trim(),
             )?,
         })
     }
+
+    /// Writes `to_string()` as a length-prefixed string.
+    pub fn write<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        write_string(file, &self.to_string())
+    }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Formats the properties in the order that the game writes them, with a leading `?`. Characters
+/// that `parse()` would misread are percent-encoded.
+impl fmt::Display for WorldProperties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "?startloc={}?sessionName={}?Visibility={}",
+            percent_encode(&self.start_loc),
+            percent_encode(&self.session_name),
+            self.visibility
+        )
+    }
+}
+
+/// Encodes `?`, and `%` where it would start an escape, so that `percent_decode()` returns `s`.
+/// Other characters are kept so that values written by the game are unchanged.
+fn percent_encode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result = String::with_capacity(s.len());
+    for (i, c) in s.char_indices() {
+        let starts_escape = c == '%'
+            && bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        match c {
+            '?' => result.push_str("%3F"),
+            '%' if starts_escape => result.push_str("%25"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Decodes `%XX` escapes in `s`. Invalid escapes are kept as they are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                result.push(byte);
+                i += 3;
+            }
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
 pub enum SessionVisiblity {
+    #[default]
     SvPrivate,
     SvFriendsOnly,
     SvInvalid,
@@ -150,23 +1439,51 @@ impl SessionVisiblity {
             0 => SvPrivate,
             1 => SvFriendsOnly,
             2 => SvInvalid,
-            _ => return Err(Error::msg(format!("invalid n: {}", n))),
+            _ => return Err(ParseError::InvalidVisibilityValue(n)),
         })
     }
 
+    pub fn to_u8(self) -> u8 {
+        match self {
+            SvPrivate => 0,
+            SvFriendsOnly => 1,
+            SvInvalid => 2,
+        }
+    }
+
     pub fn parse(s: &str) -> Result<SessionVisiblity> {
         Ok(match s {
             "SV_Private" => SvPrivate,
             "SV_FriendsOnly" => SvFriendsOnly,
             "SV_Invalid" => SvInvalid,
-            _ => return Err(Error::msg(format!("invalid s: {}", s))),
+            _ => return Err(ParseError::InvalidVisibility(s.to_string())),
         })
     }
 }
 
-impl Default for SessionVisiblity {
-    fn default() -> Self {
-        SvPrivate
+impl std::str::FromStr for SessionVisiblity {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        SessionVisiblity::parse(s)
+    }
+}
+
+impl TryFrom<u8> for SessionVisiblity {
+    type Error = ParseError;
+
+    fn try_from(n: u8) -> Result<Self> {
+        SessionVisiblity::from_u8(n)
+    }
+}
+
+impl fmt::Display for SessionVisiblity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SvPrivate => "SV_Private",
+            SvFriendsOnly => "SV_FriendsOnly",
+            SvInvalid => "SV_Invalid",
+        })
     }
 }
 
@@ -182,169 +1499,2406 @@ pub enum SaveObject {
         type_path: String,
         root_object: String,
         instance_name: String,
+        /// Whether the game applies the stored transform when loading. The transform is stored
+        /// even if this is false.
         need_transform: bool,
         rotation: Vector4,
         position: Vector3,
         scale: Vector3,
+        /// True if the entity is part of the level itself, false if it was spawned at runtime
+        /// (built by the player, for example).
         was_placed_in_level: bool,
     },
 }
 
-impl SaveObject {
-    pub fn parse<R>(file: &mut R) -> Result<Self>
-    where
-        R: Read,
-    {
-        let object_type = file.read_i32::<L>()?;
-        Ok(match object_type {
-            0 => SaveObject::SaveComponent {
-                type_path: read_string(file)?,
-                root_object: read_string(file)?,
-                instance_name: read_string(file)?,
-                parent_entity_name: read_string(file)?,
-            },
-            1 => SaveObject::SaveEntity {
-                type_path: read_string(file)?,
-                root_object: read_string(file)?,
-                instance_name: read_string(file)?,
-                need_transform: file.read_i32::<L>()? == 1,
-                rotation: Vector4::parse(file)?,
-                position: Vector3::parse(file)?,
-                scale: Vector3::parse(file)?,
-                was_placed_in_level: file.read_i32::<L>()? == 1,
-            },
-            n => return Err(Error::msg(format!("unknown object type: {}", n))),
-        })
+impl SaveObject {
+    /// Level that `new_entity()` and `new_component()` put objects in.
+    pub const DEFAULT_ROOT_OBJECT: &'static str = "Persistent_Level";
+
+    /// Returns an entity in `DEFAULT_ROOT_OBJECT` that needs its transform and was placed in the
+    /// level. Use `Transform::IDENTITY` for an entity at the origin.
+    pub fn new_entity(type_path: &str, instance_name: &str, transform: Transform) -> Self {
+        SaveObject::SaveEntity {
+            type_path: type_path.to_string(),
+            root_object: SaveObject::DEFAULT_ROOT_OBJECT.to_string(),
+            instance_name: instance_name.to_string(),
+            need_transform: true,
+            rotation: transform.rotation,
+            position: transform.translation,
+            scale: transform.scale,
+            was_placed_in_level: true,
+        }
+    }
+
+    /// Returns a component of the entity named `parent_entity_name`, in `DEFAULT_ROOT_OBJECT`.
+    pub fn new_component(type_path: &str, instance_name: &str, parent_entity_name: &str) -> Self {
+        SaveObject::SaveComponent {
+            type_path: type_path.to_string(),
+            root_object: SaveObject::DEFAULT_ROOT_OBJECT.to_string(),
+            instance_name: instance_name.to_string(),
+            parent_entity_name: parent_entity_name.to_string(),
+        }
+    }
+
+    /// Same as `parse_with_options()` with the default options.
+    pub fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        SaveObject::parse_with_options(file, &ParseOptions::default())
+    }
+
+    /// Reads an object header. Returns an error if `options.reject_non_finite` is set and the
+    /// transform is not finite. `options.type_path_filter` is not applied here.
+    pub fn parse_with_options<R>(file: &mut R, options: &ParseOptions) -> Result<Self>
+    where
+        R: Read,
+    {
+        let object = SaveObject::read(file)?;
+        if options.reject_non_finite && object.transform().is_some_and(|t| !t.is_finite()) {
+            return Err(ParseError::NonFiniteTransform(
+                object.instance_name().to_string(),
+            ));
+        }
+        Ok(object)
+    }
+
+    fn read<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let object_type = file.read_i32::<L>()?;
+        Ok(match object_type {
+            0 => SaveObject::SaveComponent {
+                type_path: read_string(file)?,
+                root_object: read_string(file)?,
+                instance_name: read_string(file)?,
+                parent_entity_name: read_string(file)?,
+            },
+            1 => {
+                let type_path = read_string(file)?;
+                let root_object = read_string(file)?;
+                let instance_name = read_string(file)?;
+                let need_transform = read_bool(file)?;
+                let transform = Transform::parse(file)?;
+                SaveObject::SaveEntity {
+                    type_path,
+                    root_object,
+                    instance_name,
+                    need_transform,
+                    rotation: transform.rotation,
+                    position: transform.translation,
+                    scale: transform.scale,
+                    was_placed_in_level: read_bool(file)?,
+                }
+            }
+            n => return Err(ParseError::UnknownObjectType(n)),
+        })
+    }
+
+    /// Writes the object header in the same format as `parse()`.
+    pub fn write<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        match self {
+            SaveObject::SaveComponent {
+                type_path,
+                root_object,
+                instance_name,
+                parent_entity_name,
+            } => {
+                file.write_i32::<L>(0)?;
+                write_string(file, type_path)?;
+                write_string(file, root_object)?;
+                write_string(file, instance_name)?;
+                write_string(file, parent_entity_name)?;
+            }
+            SaveObject::SaveEntity {
+                type_path,
+                root_object,
+                instance_name,
+                need_transform,
+                rotation,
+                position,
+                scale,
+                was_placed_in_level,
+            } => {
+                file.write_i32::<L>(1)?;
+                write_string(file, type_path)?;
+                write_string(file, root_object)?;
+                write_string(file, instance_name)?;
+                file.write_i32::<L>((*need_transform).into())?;
+                Transform {
+                    translation: *position,
+                    rotation: *rotation,
+                    scale: *scale,
+                }
+                .write(file)?;
+                file.write_i32::<L>((*was_placed_in_level).into())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn type_path(&self) -> &str {
+        match self {
+            SaveObject::SaveComponent { type_path, .. }
+            | SaveObject::SaveEntity { type_path, .. } => type_path,
+        }
+    }
+
+    /// Name of the level that the object is in.
+    pub fn root_object(&self) -> &str {
+        match self {
+            SaveObject::SaveComponent { root_object, .. }
+            | SaveObject::SaveEntity { root_object, .. } => root_object,
+        }
+    }
+
+    pub fn instance_name(&self) -> &str {
+        match self {
+            SaveObject::SaveComponent { instance_name, .. }
+            | SaveObject::SaveEntity { instance_name, .. } => instance_name,
+        }
+    }
+
+    fn instance_name_mut(&mut self) -> &mut String {
+        match self {
+            SaveObject::SaveComponent { instance_name, .. }
+            | SaveObject::SaveEntity { instance_name, .. } => instance_name,
+        }
+    }
+
+    /// Returns the identity of this object, which is its `instance_name`.
+    pub fn id(&self) -> ObjectId {
+        ObjectId(self.instance_name().to_string())
+    }
+
+    /// Returns the entity's transform, or `None` if this is a component.
+    pub fn transform(&self) -> Option<Transform> {
+        self.as_entity().map(|entity| entity.transform())
+    }
+
+    /// Returns a view of the entity fields, or `None` if this is a component.
+    pub fn as_entity(&self) -> Option<SaveEntityView<'_>> {
+        match self {
+            SaveObject::SaveEntity {
+                type_path,
+                root_object,
+                instance_name,
+                need_transform,
+                rotation,
+                position,
+                scale,
+                was_placed_in_level,
+            } => Some(SaveEntityView {
+                type_path,
+                root_object,
+                instance_name,
+                need_transform: *need_transform,
+                rotation: *rotation,
+                position: *position,
+                scale: *scale,
+                was_placed_in_level: *was_placed_in_level,
+            }),
+            SaveObject::SaveComponent { .. } => None,
+        }
+    }
+
+    /// Returns a view that can edit the entity's transform, or `None` if this is a component.
+    pub fn as_entity_mut(&mut self) -> Option<SaveEntityViewMut<'_>> {
+        match self {
+            SaveObject::SaveEntity {
+                type_path,
+                instance_name,
+                rotation,
+                position,
+                scale,
+                ..
+            } => Some(SaveEntityViewMut {
+                type_path,
+                instance_name,
+                rotation,
+                position,
+                scale,
+            }),
+            SaveObject::SaveComponent { .. } => None,
+        }
+    }
+
+    /// Returns a view of the component fields, or `None` if this is an entity.
+    pub fn as_component(&self) -> Option<SaveComponentView<'_>> {
+        match self {
+            SaveObject::SaveComponent {
+                type_path,
+                root_object,
+                instance_name,
+                parent_entity_name,
+            } => Some(SaveComponentView {
+                type_path,
+                root_object,
+                instance_name,
+                parent_entity_name,
+            }),
+            SaveObject::SaveEntity { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for SaveObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveObject::SaveComponent {
+                type_path,
+                instance_name,
+                ..
+            } => write!(f, "SaveComponent {} ({})", type_path, instance_name),
+            SaveObject::SaveEntity {
+                type_path,
+                instance_name,
+                ..
+            } => write!(f, "SaveEntity {} ({})", type_path, instance_name),
+        }
+    }
+}
+
+/// Identifies a `SaveObject` by its `instance_name`. `SaveObject` contains floats so it cannot
+/// implement `Eq` or `Hash` itself.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(pub String);
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Borrowed view of a `SaveObject::SaveEntity`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SaveEntityView<'a> {
+    type_path: &'a str,
+    root_object: &'a str,
+    instance_name: &'a str,
+    need_transform: bool,
+    rotation: Vector4,
+    position: Vector3,
+    scale: Vector3,
+    was_placed_in_level: bool,
+}
+
+impl<'a> SaveEntityView<'a> {
+    pub fn type_path(&self) -> &'a str {
+        self.type_path
+    }
+
+    pub fn root_object(&self) -> &'a str {
+        self.root_object
+    }
+
+    pub fn instance_name(&self) -> &'a str {
+        self.instance_name
+    }
+
+    pub fn need_transform(&self) -> bool {
+        self.need_transform
+    }
+
+    pub fn rotation(&self) -> Vector4 {
+        self.rotation
+    }
+
+    pub fn position(&self) -> Vector3 {
+        self.position
+    }
+
+    pub fn scale(&self) -> Vector3 {
+        self.scale
+    }
+
+    pub fn was_placed_in_level(&self) -> bool {
+        self.was_placed_in_level
+    }
+
+    /// Returns true if the entity was spawned at runtime rather than placed in the level.
+    pub fn is_runtime_spawned(&self) -> bool {
+        !self.was_placed_in_level
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: self.position,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+}
+
+/// Mutable view of a `SaveObject::SaveEntity`. Only the transform can be changed; names are
+/// referenced by other objects, so use `SaveFile::rename_object()` to change them.
+#[derive(Debug, PartialEq)]
+pub struct SaveEntityViewMut<'a> {
+    type_path: &'a str,
+    instance_name: &'a str,
+    rotation: &'a mut Vector4,
+    position: &'a mut Vector3,
+    scale: &'a mut Vector3,
+}
+
+impl<'a> SaveEntityViewMut<'a> {
+    pub fn type_path(&self) -> &'a str {
+        self.type_path
+    }
+
+    pub fn instance_name(&self) -> &'a str {
+        self.instance_name
+    }
+
+    pub fn rotation_mut(&mut self) -> &mut Vector4 {
+        self.rotation
+    }
+
+    pub fn position_mut(&mut self) -> &mut Vector3 {
+        self.position
+    }
+
+    pub fn scale_mut(&mut self) -> &mut Vector3 {
+        self.scale
+    }
+}
+
+/// Borrowed view of a `SaveObject::SaveComponent`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SaveComponentView<'a> {
+    type_path: &'a str,
+    root_object: &'a str,
+    instance_name: &'a str,
+    parent_entity_name: &'a str,
+}
+
+impl<'a> SaveComponentView<'a> {
+    pub fn type_path(&self) -> &'a str {
+        self.type_path
+    }
+
+    pub fn root_object(&self) -> &'a str {
+        self.root_object
+    }
+
+    pub fn instance_name(&self) -> &'a str {
+        self.instance_name
+    }
+
+    pub fn parent_entity_name(&self) -> &'a str {
+        self.parent_entity_name
+    }
+}
+
+/// Returns an error if `count` items of at least `min_size` bytes each do not fit in the rest of
+/// the body.
+fn check_count<R>(
+    count: u32,
+    min_size: u64,
+    decoder: &PositionTracking<R>,
+    body_length: u64,
+) -> Result<()>
+where
+    R: Read,
+{
+    let remaining = body_length.saturating_sub(decoder.position());
+    if u64::from(count) * min_size > remaining {
+        return Err(ParseError::CountExceedsBody { count, remaining });
+    }
+    Ok(())
+}
+
+/// Reads a bool stored as an i32. Values other than 0 and 1 are an error.
+fn read_bool<R>(file: &mut R) -> Result<bool>
+where
+    R: Read,
+{
+    match file.read_i32::<L>()? {
+        0 => Ok(false),
+        1 => Ok(true),
+        n => Err(ParseError::InvalidBool(n)),
+    }
+}
+
+/// Returns the root namespace of `type_path`. See `SaveFile::statistics_by_prefix()`.
+fn type_path_prefix(type_path: &str) -> &str {
+    let end_of_segment = |start: usize| {
+        type_path[start..]
+            .find(['/', '.'])
+            .map_or(type_path.len(), |i| start + i)
+    };
+
+    if !type_path.starts_with('/') {
+        return "";
+    }
+    let first = end_of_segment(1);
+    let end = match &type_path[..first] {
+        "/Script" | "/Game" if first < type_path.len() => end_of_segment(first + 1),
+        _ => first,
+    };
+    &type_path[..end]
+}
+
+/// Quotes `s` as a DOT ID.
+fn dot_id(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads one length-prefixed object data blob. The declared length is checked against the rest of
+/// the body, but the body length is declared by the file too, so the blob grows as data is read
+/// instead of being allocated at its declared size.
+fn read_object_data<R>(decoder: &mut PositionTracking<R>, body_length: u64) -> Result<Vec<u8>>
+where
+    R: Read,
+{
+    // Most that is allocated before the data has been read
+    const MAX_PREALLOCATION: u64 = 0x20000;
+
+    let signed_length = decoder.read_i32::<L>()?;
+    let length: u64 = signed_length
+        .try_into()
+        .map_err(|_| ParseError::InvalidObjectDataLength(signed_length))?;
+    let remaining = body_length.saturating_sub(decoder.position());
+    if length > remaining {
+        return Err(ParseError::DataLengthExceedsBody { length, remaining });
+    }
+
+    let mut data = Vec::with_capacity(length.min(MAX_PREALLOCATION) as usize);
+    (&mut *decoder).take(length).read_to_end(&mut data)?;
+    if data.len() as u64 != length {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(data)
+}
+
+/// Reference to an object by level and path name.
+///
+/// References are stored in one of two forms, and the enclosing structure decides which one is
+/// used:
+/// - Pair: the level name followed by the path name. Used by `collected_objects` and by object
+///   and interface properties in object data.
+/// - Single: only the path name. Used where the level is implied, such as the parent entity of a
+///   component header (`parent_entity_name`).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ObjectReference {
+    pub level_name: String,
+    pub path_name: String,
+}
+
+impl ObjectReference {
+    /// Same as `parse_pair()`.
+    pub fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        ObjectReference::parse_pair(file)
+    }
+
+    /// Reads a level name and a path name.
+    pub fn parse_pair<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        Ok(Self {
+            level_name: read_string(file)?,
+            path_name: read_string(file)?,
+        })
+    }
+
+    /// Reads only a path name. `level_name` is left empty.
+    pub fn parse_single<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        Ok(Self {
+            level_name: String::new(),
+            path_name: read_string(file)?,
+        })
+    }
+
+    /// Writes the level name and the path name.
+    pub fn write_pair<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        write_string(file, &self.level_name)?;
+        write_string(file, &self.path_name)
+    }
+
+    /// Writes only the path name. `level_name` is not written.
+    pub fn write_single<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        write_string(file, &self.path_name)
+    }
+}
+
+/// How a string is stored in a save file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StringEncoding {
+    /// Length of 0 with no data or terminator. Only valid for empty strings.
+    Empty,
+    /// Positive length followed by null-terminated bytes.
+    Utf8,
+    /// Negative length followed by null-terminated UTF-16 code units.
+    Utf16,
+}
+
+impl StringEncoding {
+    /// Returns the encoding that the game uses for `s`: `Empty` for empty strings, `Utf8` for
+    /// ASCII strings, and `Utf16` otherwise.
+    pub fn of(s: &str) -> Self {
+        if s.is_empty() {
+            StringEncoding::Empty
+        } else if s.is_ascii() {
+            StringEncoding::Utf8
+        } else {
+            StringEncoding::Utf16
+        }
+    }
+}
+
+/// Longest string that `read_string()` accepts, in bytes or UTF-16 code units including the null
+/// terminator. Writing a longer string is an error so that the file can be read back.
+const MAX_STRING_LENGTH: usize = 0x1000;
+
+/// Writes a string in the same format as `read_string()`. ASCII strings are written as
+/// null-terminated bytes and other strings as null-terminated UTF-16 with a negative length. Empty
+/// strings are written as a length of 0.
+pub fn write_string<W>(file: &mut W, s: &str) -> Result<()>
+where
+    W: Write,
+{
+    write_string_with_encoding(file, s, StringEncoding::of(s))
+}
+
+/// Writes a string with the given encoding. Use the encoding returned by
+/// `read_string_with_encoding()` to write a string back exactly as it was read. Returns an error if
+/// `encoding` is `StringEncoding::Empty` and `s` is not empty, or if the string is longer than
+/// `read_string()` accepts.
+pub fn write_string_with_encoding<W>(file: &mut W, s: &str, encoding: StringEncoding) -> Result<()>
+where
+    W: Write,
+{
+    let check_length = |length: usize| {
+        if length > MAX_STRING_LENGTH {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "string is too long",
+            ))
+        } else {
+            Ok(length as i32)
+        }
+    };
+
+    match encoding {
+        StringEncoding::Empty => {
+            if !s.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "string is not empty",
+                )
+                .into());
+            }
+            file.write_i32::<L>(0)?;
+        }
+        StringEncoding::Utf8 => {
+            let length = check_length(s.len() + 1)?;
+            file.write_i32::<L>(length)?;
+            file.write_all(s.as_bytes())?;
+            file.write_u8(0)?;
+        }
+        StringEncoding::Utf16 => {
+            let utf16: Vec<u16> = s.encode_utf16().collect();
+            let length = check_length(utf16.len() + 1)?;
+            file.write_i32::<L>(-length)?;
+            for c in utf16 {
+                file.write_u16::<L>(c)?;
+            }
+            file.write_u16::<L>(0)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a length-prefixed string.
+///
+/// A positive length is the number of bytes and a negative length is the negated number of UTF-16
+/// code units. In both cases the count includes the null terminator. A length of 0 is an empty
+/// string with no terminator. A missing terminator is tolerated.
+pub fn read_string<R>(file: &mut R) -> Result<String>
+where
+    R: Read,
+{
+    read_string_with_encoding(file).map(|(s, _)| s)
+}
+
+/// Same as `read_string()` but also returns how the string was stored. Writing the string with
+/// `write_string_with_encoding()` reproduces the original bytes unless the terminator was missing.
+pub fn read_string_with_encoding<R>(file: &mut R) -> Result<(String, StringEncoding)>
+where
+    R: Read,
+{
+    let signed_length = file.read_i32::<L>()?;
+    let length_error = || ParseError::InvalidStringLength(signed_length);
+
+    // unsigned_abs() handles i32::MIN
+    let length = signed_length.unsigned_abs() as usize;
+    if length > MAX_STRING_LENGTH {
+        return Err(length_error());
+    }
+
+    Ok(if signed_length == 0 {
+        (String::new(), StringEncoding::Empty)
+    } else if signed_length < 0 {
+        let mut buffer: Vec<u16> = vec![0; length];
+        file.read_u16_into::<L>(&mut buffer)?;
+        if buffer.last() == Some(&0) {
+            buffer.pop();
+        }
+        if let Some(index) = invalid_utf16_index(&buffer) {
+            log::warn!(
+                "replaced invalid UTF-16 at byte {} of a {}-byte string",
+                index * 2,
+                buffer.len() * 2
+            );
+        }
+        (String::from_utf16_lossy(&buffer), StringEncoding::Utf16)
+    } else {
+        let mut buffer: Vec<u8> = vec![0; length];
+        file.read_exact(&mut buffer)?;
+        if buffer.last() == Some(&0) {
+            buffer.pop();
+        }
+        if let Err(e) = std::str::from_utf8(&buffer) {
+            log::warn!(
+                "replaced invalid UTF-8 at byte {} of a {}-byte string",
+                e.valid_up_to(),
+                buffer.len()
+            );
+        }
+        (
+            String::from_utf8_lossy(&buffer).into_owned(),
+            StringEncoding::Utf8,
+        )
+    })
+}
+
+/// Returns the index of the first unpaired surrogate in `units`, which `from_utf16_lossy()`
+/// replaces.
+fn invalid_utf16_index(units: &[u16]) -> Option<usize> {
+    let mut index = 0;
+    for c in char::decode_utf16(units.iter().copied()) {
+        match c {
+            Ok(c) => index += c.len_utf16(),
+            Err(_) => return Some(index),
+        }
+    }
+    None
+}
+
+/// Ordering and comparison work like [`Vector3`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
+pub struct Vector2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vector2 {
+    pub fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        Ok(Self {
+            x: file.read_f32::<L>()?,
+            y: file.read_f32::<L>()?,
+        })
+    }
+
+    pub fn write<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        file.write_f32::<L>(self.x)?;
+        file.write_f32::<L>(self.y)?;
+        Ok(())
+    }
+
+    /// Same as [`Vector3::is_finite()`].
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+
+    /// Same as [`Vector3::total_cmp()`].
+    pub fn total_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.x.total_cmp(&other.x).then(self.y.total_cmp(&other.y))
+    }
+
+    /// Same as [`Vector3::approx_eq()`].
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (self.x - other.x).abs() <= epsilon && (self.y - other.y).abs() <= epsilon
+    }
+}
+
+/// `PartialOrd` compares the components in order and returns `None` if a compared component is
+/// NaN, so it is not suitable for sorting. Use `total_cmp()` to sort and `approx_eq()` to compare
+/// positions.
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
+pub struct Vector3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector3 {
+    pub fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        Ok(Self {
+            x: file.read_f32::<L>()?,
+            y: file.read_f32::<L>()?,
+            z: file.read_f32::<L>()?,
+        })
+    }
+
+    pub fn write<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        file.write_f32::<L>(self.x)?;
+        file.write_f32::<L>(self.y)?;
+        file.write_f32::<L>(self.z)?;
+        Ok(())
+    }
+
+    /// Returns true if no component is NaN or infinite.
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
+    /// Compares the components in order with `f32::total_cmp()`, which orders NaN after infinity.
+    pub fn total_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.x
+            .total_cmp(&other.x)
+            .then(self.y.total_cmp(&other.y))
+            .then(self.z.total_cmp(&other.z))
+    }
+
+    /// Returns true if no component differs by more than `epsilon`. NaN is never equal.
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (self.x - other.x).abs() <= epsilon
+            && (self.y - other.y).abs() <= epsilon
+            && (self.z - other.z).abs() <= epsilon
+    }
+
+    /// Euclidean distance between two points.
+    pub fn distance(&self, other: &Vector3) -> f32 {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+}
+
+impl std::ops::Add for Vector3 {
+    type Output = Vector3;
+
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3 {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+}
+
+impl fmt::Display for Vector3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    }
+}
+
+/// Ordering and comparison work like [`Vector3`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
+pub struct Vector4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vector4 {
+    pub fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        Ok(Self {
+            x: file.read_f32::<L>()?,
+            y: file.read_f32::<L>()?,
+            z: file.read_f32::<L>()?,
+            w: file.read_f32::<L>()?,
+        })
+    }
+
+    pub fn write<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        file.write_f32::<L>(self.x)?;
+        file.write_f32::<L>(self.y)?;
+        file.write_f32::<L>(self.z)?;
+        file.write_f32::<L>(self.w)?;
+        Ok(())
+    }
+
+    /// Same as [`Vector3::is_finite()`].
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite() && self.w.is_finite()
+    }
+
+    /// Same as [`Vector3::total_cmp()`].
+    pub fn total_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.x
+            .total_cmp(&other.x)
+            .then(self.y.total_cmp(&other.y))
+            .then(self.z.total_cmp(&other.z))
+            .then(self.w.total_cmp(&other.w))
+    }
+
+    /// Same as [`Vector3::approx_eq()`].
+    ///
+    /// A quaternion and its negation are the same rotation but are not approximately equal here.
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (self.x - other.x).abs() <= epsilon
+            && (self.y - other.y).abs() <= epsilon
+            && (self.z - other.z).abs() <= epsilon
+            && (self.w - other.w).abs() <= epsilon
+    }
+
+    /// Returns the quaternion that rotates by `angle` radians about `axis`. `axis` does not need
+    /// to be normalized. A zero axis returns the identity rotation.
+    pub fn from_axis_angle(axis: Vector3, angle: f32) -> Self {
+        let length = axis.distance(&Vector3::default());
+        if length == 0.0 {
+            return Self {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 1.0,
+            };
+        }
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        let scale = sin / length;
+        Self {
+            x: axis.x * scale,
+            y: axis.y * scale,
+            z: axis.z * scale,
+            w: cos,
+        }
+    }
+
+    /// Returns the normalized axis and the angle in radians, from 0 to 2π, of this quaternion.
+    /// The quaternion is normalized first. The identity rotation returns the X axis and 0. Returns
+    /// None if the quaternion is zero or not finite, because it is not a rotation.
+    pub fn to_axis_angle(&self) -> Option<(Vector3, f32)> {
+        let length = (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt();
+        if !length.is_finite() || length < 1e-6 {
+            return None;
+        }
+        let w = (self.w / length).clamp(-1.0, 1.0);
+        let angle = 2.0 * w.acos();
+        let sin = (1.0 - w * w).sqrt();
+        if sin < 1e-6 {
+            let x_axis = Vector3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            };
+            return Some((x_axis, 0.0));
+        }
+        let scale = 1.0 / (sin * length);
+        let axis = Vector3 {
+            x: self.x * scale,
+            y: self.y * scale,
+            z: self.z * scale,
+        };
+        Some((axis, angle))
+    }
+}
+
+/// Color with linear floating point channels, stored as R, G, B, A.
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
+pub struct LinearColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl LinearColor {
+    pub fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        Ok(Self {
+            r: file.read_f32::<L>()?,
+            g: file.read_f32::<L>()?,
+            b: file.read_f32::<L>()?,
+            a: file.read_f32::<L>()?,
+        })
+    }
+}
+
+/// Converts sRGB color channels to linear. Alpha is scaled without conversion.
+impl From<Color> for LinearColor {
+    fn from(color: Color) -> Self {
+        fn to_linear(c: u8) -> f32 {
+            let c = f32::from(c) / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+
+        Self {
+            r: to_linear(color.r),
+            g: to_linear(color.g),
+            b: to_linear(color.b),
+            a: f32::from(color.a) / 255.0,
+        }
+    }
+}
+
+/// 8-bit sRGB color. It is stored in B, G, R, A order.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let b = file.read_u8()?;
+        let g = file.read_u8()?;
+        let r = file.read_u8()?;
+        let a = file.read_u8()?;
+        Ok(Self { r, g, b, a })
+    }
+}
+
+/// Converts linear color channels to sRGB. Channels are clamped to 0..=1.
+impl From<LinearColor> for Color {
+    fn from(color: LinearColor) -> Self {
+        fn to_byte(c: f32) -> u8 {
+            (c.clamp(0.0, 1.0) * 255.0).round() as u8
+        }
+
+        fn to_srgb(c: f32) -> u8 {
+            let c = c.clamp(0.0, 1.0);
+            to_byte(if c <= 0.0031308 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            })
+        }
+
+        Self {
+            r: to_srgb(color.r),
+            g: to_srgb(color.g),
+            b: to_srgb(color.b),
+            a: to_byte(color.a),
+        }
+    }
+}
+
+/// Position, rotation, and scale of an entity.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3,
+    pub rotation: Vector4,
+    pub scale: Vector3,
+}
+
+impl Transform {
+    /// No translation or rotation and a scale of 1.
+    pub const IDENTITY: Transform = Transform {
+        translation: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        rotation: Vector4 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        },
+        scale: Vector3 {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+        },
+    };
+
+    /// Reads rotation, translation, and scale in that order.
+    pub fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let rotation = Vector4::parse(file)?;
+        Ok(Self {
+            translation: Vector3::parse(file)?,
+            rotation,
+            scale: Vector3::parse(file)?,
+        })
+    }
+
+    /// Returns true if no component is NaN or infinite.
+    pub fn is_finite(&self) -> bool {
+        self.translation.is_finite() && self.rotation.is_finite() && self.scale.is_finite()
+    }
+
+    /// Writes rotation, translation, and scale in that order.
+    pub fn write<W>(&self, file: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        self.rotation.write(file)?;
+        self.translation.write(file)?;
+        self.scale.write(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::iter::once;
+
+    #[test]
+    fn parse() {
+        env_logger::builder().is_test(true).try_init().unwrap();
+        let mut file = File::open("test_files/new_world.sav").unwrap();
+        let save_file = SaveFile::parse(&mut file).unwrap();
+        assert_eq!(save_file.save_header, 8);
+        assert_eq!(save_file.save_version, 25);
+        assert_eq!(save_file.build_version, 152331);
+        assert_eq!(save_file.world_type, "Persistent_Level");
+        assert_eq!(save_file.session_name, "test_file");
+        assert_eq!(save_file.play_time, Duration::seconds(17));
+        assert_eq!(save_file.save_objects.len(), 13920);
+        assert_eq!(save_file.object_data.len(), 13920);
+        assert!(save_file.collected_objects.is_empty());
+        assert!(matches!(
+            &save_file.save_objects[0],
+            SaveObject::SaveEntity { type_path, .. }
+                if type_path == "/Script/FactoryGame.FGFoliageRemoval"
+        ));
+
+        SaveFile::parse(&mut File::open("test_files/test_save2.sav").unwrap()).unwrap();
+
+        // BufReader returns short reads, which used to be mistaken for the end of a chunk
+        let file = File::open("test_files/new_world.sav").unwrap();
+        assert_eq!(
+            SaveFile::parse(&mut BufReader::new(file)).unwrap(),
+            save_file
+        );
+    }
+
+    #[test]
+    fn entities_and_components() {
+        let save_file = new_world();
+        let entity = save_file.entities().next().unwrap();
+        assert_eq!(entity.type_path(), "/Script/FactoryGame.FGFoliageRemoval");
+        assert_eq!(
+            save_file.entities().count() + save_file.components().count(),
+            save_file.save_objects.len()
+        );
+    }
+
+    #[test]
+    fn object_id() {
+        let save_file = new_world();
+        let ids: std::collections::HashSet<ObjectId> =
+            save_file.save_objects.iter().map(SaveObject::id).collect();
+        assert_eq!(ids.len(), save_file.save_objects.len());
+    }
+
+    #[test]
+    fn object_count_by_variant() {
+        let save_file = new_world();
+        assert_eq!(save_file.object_count_by_variant(), (13525, 395));
+    }
+
+    #[test]
+    fn statistics_by_prefix() {
+        let save_file = new_world();
+        let prefixes = save_file.statistics_by_prefix();
+        assert_eq!(prefixes.values().sum::<usize>(), 13920);
+        let (most_common, _) = prefixes.iter().max_by_key(|(_, count)| **count).unwrap();
+        assert!(["/Script/FactoryGame", "/Game/FactoryGame"].contains(&most_common.as_str()));
+    }
+
+    #[test]
+    fn parse_stream() {
+        /// Returns at most 7 bytes per read and does not implement Seek.
+        struct ShortReads<R>(R);
+
+        impl<R: Read> Read for ShortReads<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let length = buf.len().min(7);
+                self.0.read(&mut buf[..length])
+            }
+        }
+
+        let file = File::open("test_files/new_world.sav").unwrap();
+        assert_eq!(
+            SaveFile::parse_stream(&mut ShortReads(file)).unwrap(),
+            *new_world()
+        );
+
+        let data = std::fs::read("test_files/new_world.sav").unwrap();
+        let error = SaveFile::parse_stream(&mut &data[..data.len() - 10]).unwrap_err();
+        assert!(!matches!(error, ParseError::Truncated { .. }));
+    }
+
+    #[test]
+    fn peek_header() {
+        let mut file = File::open("test_files/new_world.sav").unwrap();
+        let header = SaveFile::peek_header(&mut file).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 143);
+        assert_eq!(header.session_name, "test_file");
+
+        let save_file = new_world();
+        assert_eq!(header, save_file.header());
+        assert!(SaveFile::from(header).save_objects.is_empty());
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut save_file = new_world().clone();
+
+        let mut count = 0;
+        for object in &save_file {
+            assert!(!object.instance_name().is_empty());
+            count += 1;
+        }
+        assert_eq!(count, save_file.save_objects.len());
+
+        let original = save_file.clone();
+        for object in &mut save_file {
+            if let Some(mut view) = object.as_entity_mut() {
+                view.position_mut().z += 1.0;
+            }
+        }
+        let (original, edited) = (original.entities().next(), save_file.entities().next());
+        assert_eq!(
+            edited.unwrap().position().z,
+            original.unwrap().position().z + 1.0
+        );
+    }
+
+    #[test]
+    fn supported_versions() {
+        let mut file = File::open("test_files/test_save2.sav").unwrap();
+        let header = SaveFile::peek_header(&mut file).unwrap();
+        assert!(SaveFile::is_supported_version(
+            header.save_header,
+            header.save_version
+        ));
+        assert!(SaveFile::supported_versions()
+            .iter()
+            .any(|supported| supported
+                .build_versions
+                .contains(&header.build_version.into())));
+
+        assert!(!SaveFile::is_supported_version(5, 25));
+        assert!(!SaveFile::is_supported_version(8, 40));
+    }
+
+    #[test]
+    fn header_byte_len() {
+        let data = std::fs::read("test_files/new_world.sav").unwrap();
+        let save_file = new_world();
+        assert_eq!(save_file.header_byte_len, 143);
+
+        let header_byte_len = save_file.header_byte_len as usize;
+        let mut header = Vec::new();
+        save_writer::SaveWriter::new(Cursor::new(&mut header))
+            .write_header(save_file)
+            .unwrap();
+        assert_eq!(header[..header_byte_len], data[..header_byte_len]);
+        assert_eq!(
+            (&data[header_byte_len..]).read_i64::<L>().unwrap(),
+            zlib_reader::PACKAGE_FILE_TAG
+        );
+    }
+
+    #[test]
+    fn parse_with_stats() {
+        let mut file = File::open("test_files/new_world.sav").unwrap();
+        let (save_file, stats) = SaveFile::parse_with_stats(&mut file).unwrap();
+        assert!(stats.chunk_count > 0);
+        assert!(stats.compressed_bytes < stats.decompressed_bytes);
+        assert_eq!(
+            stats.objects_per_chunk,
+            save_file.save_objects.len() as f64 / stats.chunk_count as f64
+        );
+        assert_eq!(stats.compressed_bytes, save_file.compressed_size);
+
+        // Errors match parse() on the same input
+        let data = std::fs::read("test_files/new_world.sav").unwrap();
+        let truncated = &data[..data.len() - 10];
+        assert_eq!(
+            SaveFile::parse_with_stats(&mut Cursor::new(truncated))
+                .unwrap_err()
+                .to_string(),
+            SaveFile::parse(&mut Cursor::new(truncated))
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(matches!(
+            SaveFile::parse_with_stats(&mut Cursor::new(truncated)).unwrap_err(),
+            ParseError::Truncated { .. }
+        ));
+    }
+
+    #[test]
+    fn compression_ratio() {
+        assert_eq!(SaveFile::default().compression_ratio(), 0.0);
+
+        let mut file = File::open("test_files/new_world.sav").unwrap();
+        let (save_file, stats) = SaveFile::parse_with_stats(&mut file).unwrap();
+        assert_eq!(save_file.compressed_size, stats.compressed_bytes);
+        assert_eq!(save_file.decompressed_size, 7514008);
+        assert!(save_file.compression_ratio() > 1.0);
+    }
+
+    #[test]
+    fn parse_auto() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let data = std::fs::read("test_files/new_world.sav").unwrap();
+        let expected = new_world();
+        assert_eq!(
+            SaveFile::parse_auto(&mut Cursor::new(&data)).unwrap(),
+            *expected
+        );
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert_eq!(
+            SaveFile::parse_auto(&mut Cursor::new(gzipped)).unwrap(),
+            *expected
+        );
+
+        assert!(SaveFile::parse_auto(&mut Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn parse_with_options() {
+        let options = ParseOptions {
+            type_path_filter: Some(
+                once("/Script/FactoryGame.FGFoliageRemoval".to_string()).collect(),
+            ),
+            ..Default::default()
+        };
+        let mut file = File::open("test_files/new_world.sav").unwrap();
+        let save_file = SaveFile::parse_with_options(&mut file, &options).unwrap();
+        assert!(!save_file.save_objects.is_empty());
+        assert!(save_file.save_objects.len() < 13920);
+        assert_eq!(save_file.object_data.len(), save_file.save_objects.len());
+        assert!(save_file
+            .save_objects
+            .iter()
+            .all(|object| object.type_path() == "/Script/FactoryGame.FGFoliageRemoval"));
+    }
+
+    #[test]
+    fn parse_lenient_bad_chunk() {
+        use crate::zlib_writer::ChunkedZLibWriter;
+        use std::io::Write;
+
+        const CHUNK_SIZE: usize = 256;
+        let mut save_file = SaveFileBuilder::new();
+        for i in 0..20 {
+            save_file = save_file.add_object(SaveObject::new_component(
+                "type",
+                &format!("component_{:02}", i),
+                "parent",
+            ));
+        }
+        let save_file = save_file.build();
+
+        let mut original = Vec::new();
+        save_file.write(&mut Cursor::new(&mut original)).unwrap();
+        let header_byte_len = SaveFile::parse(&mut Cursor::new(&original))
+            .unwrap()
+            .header_byte_len as usize;
+        let decompressed = SaveFile::decompress_body(&mut Cursor::new(&original)).unwrap();
+
+        // Rewrite the body in small chunks and overwrite the zlib header of the third one
+        let mut writer =
+            ChunkedZLibWriter::with_chunk_size(original[..header_byte_len].to_vec(), CHUNK_SIZE);
+        writer.write_i32::<L>(decompressed.len() as i32).unwrap();
+        writer.write_all(&decompressed).unwrap();
+        let mut data = writer.finish().unwrap();
+        let mut chunk_start = header_byte_len;
+        for _ in 0..2 {
+            let compressed_length = (&data[chunk_start + 16..]).read_i64::<L>().unwrap() as usize;
+            chunk_start += 48 + compressed_length;
+        }
+        data[chunk_start + 48..chunk_start + 56].fill(0xff);
+
+        assert!(SaveFile::parse(&mut Cursor::new(&data)).is_err());
+        let result = SaveFile::parse_lenient(&mut Cursor::new(&data)).unwrap();
+        assert!(result.partial);
+
+        // Objects that end before the third chunk survive. The first chunk starts with the body
+        // length, and the objects start after the object count.
+        let mut end = 4;
+        let surviving = save_file
+            .save_objects
+            .iter()
+            .take_while(|object| {
+                let mut encoded = Vec::new();
+                object.write(&mut encoded).unwrap();
+                end += encoded.len();
+                end <= 2 * CHUNK_SIZE - 4
+            })
+            .count();
+        assert!(surviving > 0 && surviving < 20);
+        assert_eq!(
+            result.save_file.save_objects,
+            save_file.save_objects[..surviving]
+        );
+    }
+
+    #[test]
+    fn reject_non_finite() {
+        let entity = entity_at(
+            "entity",
+            Vector3 {
+                x: f32::NAN,
+                y: 0.0,
+                z: 0.0,
+            },
+        );
+        let mut body = Vec::new();
+        body.extend_from_slice(&1_u32.to_le_bytes());
+        entity.write(&mut body).unwrap();
+        body.extend_from_slice(&1_u32.to_le_bytes());
+        body.extend_from_slice(&0_i32.to_le_bytes());
+        body.extend_from_slice(&0_u32.to_le_bytes());
+        let data = save_file_with_body(&body);
+
+        let save_file = SaveFile::parse(&mut Cursor::new(&data)).unwrap();
+        assert!(!save_file.save_objects[0].transform().unwrap().is_finite());
+
+        let options = ParseOptions {
+            reject_non_finite: true,
+            ..Default::default()
+        };
+        match SaveFile::parse_with_options(&mut Cursor::new(&data), &options).unwrap_err() {
+            ParseError::At { source, .. } => {
+                assert!(matches!(*source, ParseError::NonFiniteTransform(name) if name == "entity"))
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let mut file = File::open("test_files/new_world.sav").unwrap();
+        SaveFile::parse_with_options(&mut file, &options).unwrap();
+
+        let mut object = Vec::new();
+        entity.write(&mut object).unwrap();
+        assert!(SaveObject::parse(&mut object.as_slice()).is_ok());
+        assert!(SaveObject::parse_with_options(&mut object.as_slice(), &options).is_err());
+    }
+
+    #[test]
+    fn parse_lenient() {
+        let data = std::fs::read("test_files/new_world.sav").unwrap();
+
+        let result = SaveFile::parse_lenient(&mut Cursor::new(&data)).unwrap();
+        assert!(!result.partial);
+        assert!(result.error.is_none());
+        assert_eq!(
+            result.save_file,
+            SaveFile::parse(&mut Cursor::new(&data)).unwrap()
+        );
+
+        let truncated = &data[..data.len() / 4];
+        assert!(SaveFile::parse(&mut Cursor::new(truncated)).is_err());
+        let result = SaveFile::parse_lenient(&mut Cursor::new(truncated)).unwrap();
+        assert!(result.partial);
+        assert!(matches!(result.error, Some(ParseError::Truncated { .. })));
+        assert!(!result.save_file.save_objects.is_empty());
+        assert!(result.save_file.save_objects.len() < 13920);
+        assert_eq!(result.save_file.session_name, "test_file");
+
+        // Header errors are not recovered from
+        assert!(SaveFile::parse_lenient(&mut Cursor::new(&data[..10])).is_err());
+    }
+
+    /// Returns a save file with the header of new_world.sav and the given body.
+    fn save_file_with_body(body: &[u8]) -> Vec<u8> {
+        use crate::zlib_writer::ChunkedZLibWriter;
+        use byteorder::WriteBytesExt;
+        use std::io::Write;
+
+        let mut data = std::fs::read("test_files/new_world.sav").unwrap();
+        data.truncate(143);
+        let mut writer = ChunkedZLibWriter::new(data);
+        writer.write_i32::<L>(body.len() as i32).unwrap();
+        writer.write_all(body).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn empty_body() {
+        // Object count, object data count, collected object count
+        let data = save_file_with_body(&[0; 12]);
+
+        let save_file = SaveFile::parse(&mut Cursor::new(&data)).unwrap();
+        assert!(save_file.save_objects.is_empty());
+        assert!(save_file.object_data.is_empty());
+        assert!(save_file.collected_objects.is_empty());
+        assert_eq!(save_file.object_count_by_variant(), (0, 0));
+        assert_eq!(save_file.decompressed_size, 12);
+
+        let (stats_save_file, stats) = SaveFile::parse_with_stats(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(stats_save_file, save_file);
+        assert_eq!(stats.chunk_count, 1);
+        assert_eq!(stats.objects_per_chunk, 0.0);
+
+        let lenient = SaveFile::parse_lenient(&mut Cursor::new(&data)).unwrap();
+        assert!(!lenient.partial);
+        assert!(lenient.error.is_none());
+        assert_eq!(lenient.save_file, save_file);
+
+        let header = SaveFile::peek_header(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(header, save_file.header());
+    }
+
+    #[test]
+    fn truncated() {
+        let data = std::fs::read("test_files/new_world.sav").unwrap();
+        let first_chunk_end = 143 + 48 + (&data[143 + 16..]).read_i64::<L>().unwrap() as usize;
+
+        let cases = [
+            (0, TruncatedStage::Header),
+            (50, TruncatedStage::Header),
+            (143 + 20, TruncatedStage::Chunk),
+            (143 + 48 + 100, TruncatedStage::Chunk),
+            (143, TruncatedStage::Chunk),
+            (data.len() - 10, TruncatedStage::Chunk),
+            (first_chunk_end, TruncatedStage::Objects),
+        ];
+        for (length, expected_stage) in cases {
+            let error = SaveFile::parse(&mut Cursor::new(&data[..length])).unwrap_err();
+            match error {
+                ParseError::Truncated {
+                    stage,
+                    bytes_expected,
+                    bytes_available,
+                } => {
+                    assert_eq!(stage, expected_stage, "{}", length);
+                    assert!(bytes_expected > bytes_available, "{}", length);
+                    if stage == TruncatedStage::Chunk {
+                        assert_eq!(bytes_available, length as u64);
+                    }
+                }
+                e => panic!("{}: unexpected error: {:?}", length, e),
+            }
+        }
+
+        let error = SaveFile::parse(&mut Cursor::new(&data[..data.len() - 10])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "save file is incomplete: compressed chunk needs {} bytes but only {} are available",
+                data.len(),
+                data.len() - 10
+            )
+        );
+    }
+
+    #[test]
+    fn trailing_data() {
+        let mut body = Vec::new();
+        for _ in 0..3 {
+            // Object count, object data count, collected object count
+            body.extend_from_slice(&0_u32.to_le_bytes());
+        }
+        let save_file = SaveFile::parse(&mut Cursor::new(save_file_with_body(&body))).unwrap();
+        assert!(save_file.save_objects.is_empty());
+
+        body.extend_from_slice(&[1, 2, 3]);
+        let data = save_file_with_body(&body);
+        let error = SaveFile::parse(&mut Cursor::new(&data)).unwrap_err();
+        assert!(matches!(error, ParseError::TrailingData { remaining: 3 }));
+        assert!(
+            !SaveFile::parse_lenient(&mut Cursor::new(&data))
+                .unwrap()
+                .partial
+        );
+    }
+
+    #[test]
+    fn unsupported_save_header() {
+        let mut data = Cursor::new(9_i32.to_le_bytes().to_vec());
+        let error = SaveFile::parse(&mut data).unwrap_err();
+        assert_eq!(error.to_string(), "error in header at byte 4");
+        assert_eq!(
+            std::error::Error::source(&error).unwrap().to_string(),
+            "unsupported save header version: 9"
+        );
+    }
+
+    #[test]
+    fn error_position() {
+        let data = std::fs::read("test_files/new_world.sav").unwrap();
+
+        // Cut off in the middle of the session name
+        assert!(matches!(
+            SaveFile::parse(&mut Cursor::new(&data[..110])).unwrap_err(),
+            ParseError::Truncated {
+                stage: TruncatedStage::Header,
+                bytes_expected: 111,
+                bytes_available: 110,
+            }
+        ));
+        let error = SaveFile::parse_header(&mut &data[..110]).unwrap_err();
+        match &error {
+            ParseError::At {
+                section: Section::Header,
+                position: 110,
+                source,
+            } => match source.as_ref() {
+                ParseError::Io(e) => {
+                    assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+                    let io_error = std::error::Error::source(source.as_ref()).unwrap();
+                    assert!(io_error.is::<std::io::Error>());
+                }
+                e => panic!("unexpected source: {:?}", e),
+            },
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // Invalid object type in the first object
+        let mut body = Vec::new();
+        body.extend_from_slice(&1_u32.to_le_bytes());
+        body.extend_from_slice(&2_i32.to_le_bytes());
+        body.resize(32, 0);
+        let error = SaveFile::parse_objects(
+            &mut body.as_slice(),
+            body.len() as u64,
+            &mut SaveFile::default(),
+            &Default::default(),
+        )
+        .unwrap_err();
+        match error {
+            ParseError::At {
+                section: Section::Body,
+                position: 8,
+                source,
+            } => assert!(matches!(
+                *source,
+                ParseError::UnknownObject {
+                    object_type: 2,
+                    index: 0,
+                    offset: 4,
+                }
+            )),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn object_data_past_end_of_file() {
+        use crate::zlib_writer::ChunkedZLibWriter;
+        use std::io::Write;
+
+        // The declared body length leaves room for a blob of almost 2 GB, but the file ends first
+        let mut data = std::fs::read("test_files/new_world.sav").unwrap();
+        data.truncate(143);
+        let mut writer = ChunkedZLibWriter::new(data);
+        writer.write_i32::<L>(i32::MAX).unwrap();
+        writer.write_u32::<L>(1).unwrap();
+        // Component with empty strings
+        writer.write_all(&[0; 4 + 4 * 4]).unwrap();
+        writer.write_u32::<L>(1).unwrap();
+        writer.write_i32::<L>(i32::MAX - 100).unwrap();
+        writer.write_all(&[1, 2, 3]).unwrap();
+        let data = writer.finish().unwrap();
+
+        match SaveFile::parse_stream(&mut data.as_slice()).unwrap_err() {
+            ParseError::At {
+                section: Section::Body,
+                source,
+                ..
+            } => assert!(
+                matches!(*source, ParseError::Io(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+            ),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(matches!(
+            SaveFile::parse(&mut Cursor::new(&data)).unwrap_err(),
+            ParseError::Truncated {
+                stage: TruncatedStage::Objects,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn object_data_len() {
+        let save_file = new_world();
+        assert_eq!(
+            save_file.object_data_len(0),
+            Some(save_file.object_data[0].len())
+        );
+        assert_eq!(
+            save_file.object_data_len(save_file.save_objects.len()),
+            None
+        );
+    }
+
+    #[test]
+    fn count_exceeds_body() {
+        let mut body = Vec::new();
+        body.extend_from_slice(&u32::MAX.to_le_bytes());
+        body.resize(64, 0);
+        let data = save_file_with_body(&body);
+        match SaveFile::parse(&mut Cursor::new(&data)).unwrap_err() {
+            ParseError::At {
+                section: Section::Body,
+                position: 4,
+                source,
+            } => assert!(matches!(
+                *source,
+                ParseError::CountExceedsBody {
+                    count: u32::MAX,
+                    remaining: 60,
+                }
+            )),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let result = SaveFile::parse_lenient(&mut Cursor::new(&data)).unwrap();
+        assert!(result.partial);
+        assert!(result.save_file.save_objects.is_empty());
+
+        // One component with empty strings and a data blob longer than the body
+        let mut body = Vec::new();
+        body.extend_from_slice(&1_u32.to_le_bytes());
+        body.extend_from_slice(&[0; 4 + 4 * 4]);
+        body.extend_from_slice(&1_u32.to_le_bytes());
+        body.extend_from_slice(&i32::MAX.to_le_bytes());
+        body.resize(64, 0);
+        let data = save_file_with_body(&body);
+        match SaveFile::parse(&mut Cursor::new(&data)).unwrap_err() {
+            ParseError::At { source, .. } => assert!(matches!(
+                *source,
+                ParseError::DataLengthExceedsBody {
+                    length: 0x7FFFFFFF,
+                    remaining: 32,
+                }
+            )),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn set_duplicated_fields() {
+        let mut save_file = SaveFile::default();
+        save_file.set_session_name("renamed");
+        assert_eq!(save_file.session_name, "renamed");
+        assert_eq!(save_file.world_properties.session_name, "renamed");
+
+        save_file.set_visibility(SessionVisiblity::SvFriendsOnly);
+        assert_eq!(
+            save_file.session_visibility,
+            SessionVisiblity::SvFriendsOnly
+        );
+        assert_eq!(
+            save_file.world_properties.visibility,
+            SessionVisiblity::SvFriendsOnly
+        );
+    }
+
+    #[test]
+    fn set_play_time() {
+        let mut save_file = SaveFile::default();
+        save_file
+            .set_play_time(Duration::milliseconds(17_900))
+            .unwrap();
+        assert_eq!(save_file.play_time, Duration::seconds(17));
+
+        assert!(save_file
+            .set_play_time(Duration::seconds(i64::from(i32::MAX) + 1))
+            .is_err());
+        assert_eq!(save_file.play_time, Duration::seconds(17));
+    }
+
+    #[test]
+    fn time_since() {
+        let earlier = SaveFile {
+            play_time: Duration::seconds(17),
+            save_date: Utc.with_ymd_and_hms(2021, 1, 1, 12, 0, 0).unwrap(),
+            ..Default::default()
+        };
+        let later = SaveFile {
+            play_time: Duration::seconds(435),
+            save_date: Utc.with_ymd_and_hms(2021, 1, 1, 13, 30, 0).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(later.play_time_since(&earlier), Duration::seconds(418));
+        assert_eq!(later.real_time_since(&earlier), Duration::minutes(90));
+        assert_eq!(earlier.play_time_since(&later), Duration::seconds(-418));
+        assert_eq!(earlier.real_time_since(&later), Duration::minutes(-90));
+        assert_eq!(later.play_time_since(&later), Duration::zero());
+    }
+
+    #[test]
+    fn mod_status() {
+        let mut save_file = new_world().clone();
+        assert_eq!(save_file.mod_status(), ModStatus::Unmodded);
+
+        let mod_meta_data = r#"{"Version":1,"Mods":[]}"#;
+        save_file.set_mod_status(ModStatus::Modded(mod_meta_data));
+        assert!(save_file.is_modded_save);
+        assert_eq!(save_file.mod_status(), ModStatus::Modded(mod_meta_data));
+
+        // Metadata without the flag is ignored
+        save_file.is_modded_save = false;
+        assert_eq!(save_file.mod_status(), ModStatus::Unmodded);
+
+        save_file.set_mod_status(ModStatus::Unmodded);
+        assert!(save_file.mod_meta_data.is_empty());
+    }
+
+    #[test]
+    fn axis_angle() {
+        use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2};
+
+        let axes = [
+            Vector3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            },
+        ];
+        for axis in axes {
+            let rotation = Vector4::from_axis_angle(axis, FRAC_PI_2);
+            let expected = [
+                axis.x * FRAC_1_SQRT_2,
+                axis.y * FRAC_1_SQRT_2,
+                axis.z * FRAC_1_SQRT_2,
+                FRAC_1_SQRT_2,
+            ];
+            let actual = [rotation.x, rotation.y, rotation.z, rotation.w];
+            for (a, b) in actual.iter().zip(&expected) {
+                assert!((a - b).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+            }
+
+            let (result_axis, angle) = rotation.to_axis_angle().unwrap();
+            assert!(result_axis.distance(&axis) < 1e-6);
+            assert!((angle - FRAC_PI_2).abs() < 1e-6);
+        }
+
+        // Axis does not have to be normalized
+        let (axis, angle) = Vector4::from_axis_angle(
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 5.0,
+            },
+            1.0,
+        )
+        .to_axis_angle()
+        .unwrap();
+        assert!(axis.distance(&axes[2]) < 1e-6);
+        assert!((angle - 1.0).abs() < 1e-6);
+
+        let identity = Vector4::from_axis_angle(axes[1], 0.0);
+        assert_eq!(identity.w, 1.0);
+        assert_eq!(identity.to_axis_angle(), Some((axes[0], 0.0)));
+        assert_eq!(Vector4::from_axis_angle(Vector3::default(), 1.0), identity);
+
+        // Not normalized
+        let (axis, angle) = Vector4 {
+            x: 0.0,
+            y: 0.0,
+            z: 2.0,
+            w: 2.0,
+        }
+        .to_axis_angle()
+        .unwrap();
+        assert!(axis.distance(&axes[2]) < 1e-6);
+        assert!((angle - FRAC_PI_2).abs() < 1e-6);
+
+        assert_eq!(Vector4::default().to_axis_angle(), None);
+        let nan = Vector4 {
+            x: f32::NAN,
+            ..identity
+        };
+        assert_eq!(nan.to_axis_angle(), None);
+    }
+
+    #[test]
+    fn save_date_in() {
+        use chrono::{FixedOffset, Timelike};
+
+        let save_file = SaveFile {
+            save_date: Utc.with_ymd_and_hms(2021, 5, 16, 7, 25, 47).unwrap(),
+            ..Default::default()
+        };
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        let date = save_file.save_date_in(&tokyo);
+        assert_eq!(date.hour(), 16);
+        assert_eq!(date.to_rfc3339(), "2021-05-16T16:25:47+09:00");
+        assert_eq!(date, save_file.save_date);
+        assert_eq!(save_file.save_date_local(), save_file.save_date);
+    }
+
+    /// Returns new_world.sav, which is parsed once and shared by the tests.
+    pub(crate) fn new_world() -> &'static SaveFile {
+        static NEW_WORLD: std::sync::OnceLock<SaveFile> = std::sync::OnceLock::new();
+        NEW_WORLD.get_or_init(|| {
+            let mut file = File::open("test_files/new_world.sav").unwrap();
+            SaveFile::parse(&mut file).unwrap()
+        })
+    }
+
+    fn component(instance_name: &str, parent_entity_name: &str) -> SaveObject {
+        SaveObject::new_component("type", instance_name, parent_entity_name)
+    }
+
+    fn entity_at(instance_name: &str, position: Vector3) -> SaveObject {
+        SaveObject::SaveEntity {
+            type_path: "type".to_string(),
+            root_object: "Persistent_Level".to_string(),
+            instance_name: instance_name.to_string(),
+            need_transform: true,
+            rotation: Default::default(),
+            position,
+            scale: Default::default(),
+            was_placed_in_level: false,
+        }
+    }
+
+    #[test]
+    fn objects_in_radius() {
+        let save_file = SaveFileBuilder::new()
+            .add_object(entity_at("origin", Vector3::default()))
+            .add_object(entity_at(
+                "boundary",
+                Vector3 {
+                    x: 3.0,
+                    y: 4.0,
+                    z: 0.0,
+                },
+            ))
+            .add_object(entity_at(
+                "outside",
+                Vector3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 5.1,
+                },
+            ))
+            .add_object(component("component", "origin"))
+            .build();
+
+        let ids = |objects: Vec<&SaveObject>| -> Vec<ObjectId> {
+            objects.into_iter().map(SaveObject::id).collect()
+        };
+        assert_eq!(
+            ids(save_file.objects_in_radius(Vector3::default(), 5.0)),
+            vec![
+                ObjectId("origin".to_string()),
+                ObjectId("boundary".to_string())
+            ]
+        );
+        assert_eq!(
+            ids(save_file.objects_in_radius(Vector3::default(), 4.9)),
+            vec![ObjectId("origin".to_string())]
+        );
+        assert!(save_file
+            .objects_in_radius(
+                Vector3 {
+                    x: 100.0,
+                    y: 0.0,
+                    z: 0.0
+                },
+                1.0
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn rename_object() {
+        let mut save_file = SaveFileBuilder::new()
+            .add_object(entity_at("entity", Vector3::default()))
+            .add_object(component("component", "entity"))
+            .add_object(component("other", "other_entity"))
+            .build();
+        save_file.collected_objects.push(ObjectReference {
+            level_name: "Persistent_Level".to_string(),
+            path_name: "entity".to_string(),
+        });
+
+        assert!(save_file.rename_object("entity", "component").is_err());
+        assert!(save_file.rename_object("missing", "new").is_err());
+        assert!(save_file.rename_object("missing", "missing").is_err());
+
+        let original = save_file.clone();
+        save_file.rename_object("entity", "entity").unwrap();
+        assert_eq!(save_file, original);
+
+        save_file.rename_object("entity", "renamed").unwrap();
+        assert_eq!(save_file.save_objects[0].instance_name(), "renamed");
+        assert_eq!(save_file.save_objects[1], component("component", "renamed"));
+        assert_eq!(
+            save_file.save_objects[2],
+            component("other", "other_entity")
+        );
+        assert_eq!(save_file.collected_objects[0].path_name, "renamed");
+    }
+
+    #[test]
+    fn objects_mut() {
+        let mut save_file = SaveFileBuilder::new()
+            .add_object(entity_at("entity", Vector3::default()))
+            .build();
+        *save_file.objects_mut()[0].instance_name_mut() = "renamed".to_string();
+        let objects = save_file.into_objects();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].instance_name(), "renamed");
+    }
+
+    #[test]
+    fn filter_objects() {
+        let mut save_file = SaveFileBuilder::new()
+            .session_name("filtered")
+            .add_object(entity_at("a", Vector3::default()))
+            .add_object(entity_at("b", Vector3::default()))
+            .add_object(entity_at("c", Vector3::default()))
+            .build();
+        save_file.object_data = vec![vec![0], vec![1], vec![2]];
+        save_file.compressed_size = 10;
+        save_file.decompressed_size = 20;
+        save_file.header_byte_len = 30;
+
+        let filtered = save_file.filter_objects(|object| object.instance_name() != "b");
+        assert_eq!(filtered.session_name, "filtered");
+        let names: Vec<&str> = filtered
+            .save_objects
+            .iter()
+            .map(SaveObject::instance_name)
+            .collect();
+        assert_eq!(names, ["a", "c"]);
+        assert_eq!(filtered.object_data, [vec![0], vec![2]]);
+        assert_eq!(filtered.compressed_size, 0);
+        assert_eq!(filtered.decompressed_size, 0);
+        assert_eq!(filtered.header_byte_len, 0);
+    }
+
+    #[test]
+    fn structurally_eq() {
+        let save_file = SaveFileBuilder::new()
+            .add_object(entity_at("entity", Vector3::default()))
+            .build();
+        let mut other = save_file.clone();
+        other.play_time = Duration::seconds(10);
+        other.save_date = Utc::now();
+        other.compressed_size = 100;
+        other.decompressed_size = 200;
+        other.header_byte_len = 300;
+        assert_ne!(save_file, other);
+        assert!(save_file.structurally_eq(&other));
+
+        other.set_session_name("renamed");
+        assert!(!save_file.structurally_eq(&other));
+
+        // One component whose type path is ASCII stored as UTF-16, which write() stores as UTF-8
+        let mut body = Vec::new();
+        body.extend_from_slice(&1_u32.to_le_bytes());
+        body.extend_from_slice(&0_i32.to_le_bytes());
+        body.extend_from_slice(&(-2_i32).to_le_bytes());
+        body.extend_from_slice(&[b'a', 0, 0, 0]);
+        body.extend_from_slice(&[0; 3 * 4]);
+        // Object data count, empty data, collected object count
+        body.extend_from_slice(&1_u32.to_le_bytes());
+        body.extend_from_slice(&[0; 2 * 4]);
+        let save_file = SaveFile::parse(&mut Cursor::new(save_file_with_body(&body))).unwrap();
+
+        let mut data = Cursor::new(Vec::new());
+        save_file.write(&mut data).unwrap();
+        data.set_position(0);
+        let written = SaveFile::parse(&mut data).unwrap();
+        assert_ne!(written.decompressed_size, save_file.decompressed_size);
+        assert!(written.structurally_eq(&save_file));
     }
-}
 
-pub fn read_string<R>(file: &mut R) -> Result<String>
-where
-    R: Read,
-{
-    const MAX_LENGTH: usize = 0x1000;
-    let length_error = || Error::msg("invalid length");
-    let signed_length = file.read_i32::<L>()?;
+    #[test]
+    fn translate_all() {
+        let mut save_file = SaveFileBuilder::new()
+            .add_object(entity_at(
+                "a",
+                Vector3 {
+                    x: 1.0,
+                    y: 2.0,
+                    z: 3.0,
+                },
+            ))
+            .add_object(component("a.component", "a"))
+            .add_object(entity_at("b", Vector3::default()))
+            .build();
+        let components = save_file.components().count();
 
-    Ok(if signed_length < 0 {
-        // Negation fails with minimum i32
-        if signed_length == i32::MIN {
-            return Err(length_error());
-        }
+        save_file.translate_all(Vector3 {
+            x: 10.0,
+            y: -1.0,
+            z: 0.5,
+        });
+        let positions: Vec<Vector3> = save_file.entities().map(|e| e.position()).collect();
+        assert_eq!(
+            positions,
+            [
+                Vector3 {
+                    x: 11.0,
+                    y: 1.0,
+                    z: 3.5
+                },
+                Vector3 {
+                    x: 10.0,
+                    y: -1.0,
+                    z: 0.5
+                }
+            ]
+        );
+        assert_eq!(save_file.components().count(), components);
 
-        let mut buffer: Vec<u16> = Vec::new();
-        let length = ((-signed_length) as usize).saturating_sub(1) / 2;
-        if length > MAX_LENGTH {
-            return Err(length_error());
-        }
-        buffer.resize(length, 0);
-        file.read_u16_into::<L>(&mut buffer)?;
-        String::from_utf16_lossy(&buffer)
-    } else {
-        let mut buffer: Vec<u8> = Vec::new();
-        let length = (signed_length as usize).saturating_sub(1);
-        if length > MAX_LENGTH {
-            return Err(length_error());
+        for mut entity in save_file.entities_mut() {
+            entity.scale_mut().x = 2.0;
         }
-        buffer.resize(length, b'\0');
-        file.read_exact(&mut buffer)?;
-        if length > 0 {
-            // Skip null char
-            file.read_u8()?;
+        assert!(save_file.entities().all(|e| e.scale().x == 2.0));
+    }
+
+    #[test]
+    fn translate_all_round_trip() {
+        let original = new_world();
+        let offset = Vector3 {
+            x: 1000.0,
+            y: -500.0,
+            z: 0.0,
+        };
+        let mut save_file = original.clone();
+        save_file.translate_all(offset);
+
+        let mut data = Cursor::new(Vec::new());
+        save_file.write(&mut data).unwrap();
+        data.set_position(0);
+        let written = SaveFile::parse(&mut data).unwrap();
+        for (before, after) in original.entities().zip(written.entities()) {
+            assert_eq!(after.position(), before.position() + offset);
+            assert_eq!(after.rotation(), before.rotation());
+            assert_eq!(after.scale(), before.scale());
         }
-        String::from_utf8_lossy(&buffer).into_owned()
-    })
-}
+        assert_eq!(written.object_data, original.object_data);
+    }
 
-#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
-pub struct Vector2 {
-    pub x: f32,
-    pub y: f32,
+    #[test]
+    fn to_dot() {
+        let save_file = SaveFileBuilder::new()
+            .add_object(entity_at("entity", Vector3::default()))
+            .add_object(component("entity.\"component\"", "entity"))
+            .build();
+        assert_eq!(
+            save_file.to_dot(),
+            r#"digraph save {
+    "entity" [style=filled, fillcolor=lightblue];
+    "entity.\"component\"" [style=filled, fillcolor=lightgray];
+    "entity.\"component\"" -> "entity";
 }
+"#
+        );
+    }
 
-impl Vector2 {
-    pub fn parse<R>(file: &mut R) -> Result<Self>
-    where
-        R: Read,
-    {
-        Ok(Self {
-            x: file.read_f32::<L>()?,
-            y: file.read_f32::<L>()?,
-        })
+    #[test]
+    fn need_transform_false() {
+        // Most entities in new_world.sav do not need their transform. If the transform were only
+        // stored for those that do, parsing would not end exactly at the end of the body.
+        let save_file = new_world();
+        let entity = save_file
+            .entities()
+            .find(|entity| !entity.need_transform())
+            .unwrap();
+        assert!(entity.transform().is_finite());
+        assert_eq!(entity.scale().x, 1.0);
+
+        let object = SaveObject::SaveEntity {
+            type_path: "type".to_string(),
+            root_object: "Persistent_Level".to_string(),
+            instance_name: "entity".to_string(),
+            need_transform: false,
+            rotation: Vector4::from_axis_angle(Vector3::default(), 0.0),
+            position: Vector3 {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            },
+            scale: Vector3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            was_placed_in_level: true,
+        };
+        let mut data = Vec::new();
+        object.write(&mut data).unwrap();
+        let mut reader = data.as_slice();
+        assert_eq!(SaveObject::parse(&mut reader).unwrap(), object);
+        assert!(reader.is_empty());
     }
-}
 
-#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
-pub struct Vector3 {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-}
+    #[test]
+    fn resolve() {
+        let object = |root_object: &str, parent_entity_name: &str| SaveObject::SaveComponent {
+            type_path: "type".to_string(),
+            root_object: root_object.to_string(),
+            instance_name: "Persistent_Level:PersistentLevel.Object_0".to_string(),
+            parent_entity_name: parent_entity_name.to_string(),
+        };
+        let save_file = SaveFileBuilder::new()
+            .add_object(object("Level_A", "a"))
+            .add_object(object("Level_B", "b"))
+            .build();
 
-impl Vector3 {
-    pub fn parse<R>(file: &mut R) -> Result<Self>
-    where
-        R: Read,
-    {
-        Ok(Self {
-            x: file.read_f32::<L>()?,
-            y: file.read_f32::<L>()?,
-            z: file.read_f32::<L>()?,
-        })
+        let reference = |level_name: &str| ObjectReference {
+            level_name: level_name.to_string(),
+            path_name: "Persistent_Level:PersistentLevel.Object_0".to_string(),
+        };
+        let resolved = save_file.resolve(&reference("Level_B")).unwrap();
+        assert_eq!(resolved.as_component().unwrap().parent_entity_name(), "b");
+        let resolved = save_file.resolve(&reference("Level_A")).unwrap();
+        assert_eq!(resolved.as_component().unwrap().parent_entity_name(), "a");
+        assert!(save_file.resolve(&reference("Level_C")).is_none());
     }
-}
 
-#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
-pub struct Vector4 {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-    pub w: f32,
-}
+    #[test]
+    fn new_object() {
+        let transform = Transform {
+            translation: Vector3 {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            },
+            ..Transform::IDENTITY
+        };
+        let entity = SaveObject::new_entity("/Script/Type.Entity", "entity", transform);
+        let component = SaveObject::new_component("/Script/Type.Component", "component", "entity");
+        assert_eq!(entity.transform(), Some(transform));
+        assert!(entity.as_entity().unwrap().was_placed_in_level());
+        assert_eq!(component.root_object(), "Persistent_Level");
+        assert_eq!(
+            component.as_component().unwrap().parent_entity_name(),
+            "entity"
+        );
 
-impl Vector4 {
-    pub fn parse<R>(file: &mut R) -> Result<Self>
-    where
-        R: Read,
-    {
-        Ok(Self {
-            x: file.read_f32::<L>()?,
-            y: file.read_f32::<L>()?,
-            z: file.read_f32::<L>()?,
-            w: file.read_f32::<L>()?,
-        })
+        let mut data = Vec::new();
+        entity.write(&mut data).unwrap();
+        component.write(&mut data).unwrap();
+        let mut reader = data.as_slice();
+        assert_eq!(SaveObject::parse(&mut reader).unwrap(), entity);
+        assert_eq!(SaveObject::parse(&mut reader).unwrap(), component);
+        assert!(reader.is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::File;
-    use std::io::{BufReader, Cursor};
-    use std::iter::once;
+    #[test]
+    fn find_objects_by_class_suffix() {
+        let object = |type_path: &str, instance_name: &str| {
+            SaveObject::new_component(type_path, instance_name, "")
+        };
+        let save_file = SaveFileBuilder::new()
+            .add_object(object(
+                "/Game/FactoryGame/Buildable/Factory/ConstructorMk1/Build_ConstructorMk1.Build_ConstructorMk1_C",
+                "constructor",
+            ))
+            .add_object(object(
+                "/Game/FactoryGame/Buildable/Factory/AssemblerMk1/Build_AssemblerMk1.Build_AssemblerMk1_C",
+                "assembler",
+            ))
+            .build();
+
+        let found = save_file.find_objects_by_class_suffix("Constructor");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].instance_name(), "constructor");
+        assert_eq!(save_file.find_objects_by_class_suffix("mk1_c").len(), 2);
+        assert!(save_file
+            .find_objects_by_class_suffix("Manufacturer")
+            .is_empty());
+    }
 
     #[test]
-    fn parse() {
-        env_logger::builder().is_test(true).try_init().unwrap();
-        let mut file = File::open("test_files/new_world.sav").unwrap();
-        let save_file = SaveFile::parse(&mut file).unwrap();
-        assert_eq!(save_file.save_header, 8);
-        assert_eq!(save_file.save_version, 25);
+    fn object_references() {
+        let save_file = SaveFileBuilder::new()
+            .add_object(entity_at("entity", Vector3::default()))
+            .add_object(component("entity.a", "entity"))
+            .add_object(component("entity.b", "entity"))
+            .add_object(component("orphan", ""))
+            .build();
+        let references = save_file.object_references();
+        assert_eq!(references.len(), 2);
+        assert_eq!(
+            references[0],
+            (
+                ObjectId("entity.a".to_string()),
+                ObjectReference {
+                    level_name: "Persistent_Level".to_string(),
+                    path_name: "entity".to_string(),
+                }
+            )
+        );
+
+        let save_file = new_world();
+        assert_eq!(save_file.object_references().len(), 395);
+    }
+
+    #[test]
+    fn save_file_builder() {
+        let save_file = SaveFileBuilder::new().build();
+        assert_eq!(
+            save_file,
+            SaveFile {
+                save_header: SaveFile::SAVE_HEADER_VERSION,
+                save_version: SaveFile::TESTED_SAVE_VERSION,
+                build_version: BuildVersion::NEWEST_TESTED.0,
+                ..Default::default()
+            }
+        );
+        assert!(SaveFile::is_supported_version(
+            save_file.save_header,
+            save_file.save_version
+        ));
+
+        let object = component("instance", "parent");
+        let save_file = SaveFileBuilder::new()
+            .session_name("session")
+            .build_version(152331)
+            .world_type("Persistent_Level")
+            .visibility(SessionVisiblity::SvFriendsOnly)
+            .add_object(object.clone())
+            .build();
+        assert_eq!(save_file.session_name, "session");
+        assert_eq!(save_file.world_properties.session_name, "session");
         assert_eq!(save_file.build_version, 152331);
         assert_eq!(save_file.world_type, "Persistent_Level");
-        assert_eq!(save_file.session_name, "test_file");
-        assert_eq!(save_file.save_objects.len(), 13920);
-        assert!(matches!(
-            &save_file.save_objects[0],
-            SaveObject::SaveEntity { type_path, .. }
-                if type_path == "/Script/FactoryGame.FGFoliageRemoval"
-        ));
+        assert_eq!(
+            save_file.session_visibility,
+            SessionVisiblity::SvFriendsOnly
+        );
+        assert_eq!(
+            save_file.world_properties.visibility,
+            SessionVisiblity::SvFriendsOnly
+        );
+        assert_eq!(save_file.save_objects, vec![object]);
 
-        SaveFile::parse(&mut File::open("test_files/test_save2.sav").unwrap()).unwrap();
+        let mut data = Cursor::new(Vec::new());
+        save_file.write(&mut data).unwrap();
+        data.set_position(0);
+        assert!(SaveFile::parse(&mut data)
+            .unwrap()
+            .structurally_eq(&save_file));
+    }
 
-        // Demonstrates how it fails when reading from BufReader
-        let file = File::open("test_files/new_world.sav").unwrap();
-        assert!(SaveFile::parse(&mut BufReader::new(file)).is_err());
+    #[test]
+    fn type_path_prefix() {
+        let cases = &[
+            (
+                "/Script/FactoryGame.FGFoliageRemoval",
+                "/Script/FactoryGame",
+            ),
+            (
+                "/Game/FactoryGame/Buildable/Build_Foundation.Build_Foundation_C",
+                "/Game/FactoryGame",
+            ),
+            ("/SomeMod/Buildings/Build_Thing.Build_Thing_C", "/SomeMod"),
+            ("/Script", "/Script"),
+            ("/Script/", "/Script/"),
+            ("", ""),
+            ("FactoryGame", ""),
+        ];
+        for (type_path, expected) in cases {
+            assert_eq!(
+                super::type_path_prefix(type_path),
+                *expected,
+                "{}",
+                type_path
+            );
+        }
+    }
+
+    #[test]
+    fn save_type() {
+        assert_eq!(
+            SaveType::from_filename("test_file_autosave_0.sav"),
+            SaveType::Auto
+        );
+        assert_eq!(
+            SaveType::from_filename(r"C:\SaveGames\123\my world_autosave_2.SAV"),
+            SaveType::Auto
+        );
+        assert_eq!(SaveType::from_filename("a_autosave_10"), SaveType::Auto);
+        assert_eq!(SaveType::from_filename("new_world.sav"), SaveType::Manual);
+        assert_eq!(SaveType::from_filename("_autosave_0.sav"), SaveType::Manual);
+        assert_eq!(SaveType::from_filename("a_autosave_.sav"), SaveType::Manual);
+        assert_eq!(
+            SaveType::from_filename("a_autosave_0/b.sav"),
+            SaveType::Manual
+        );
+        assert_eq!(SaveType::from_filename("あ"), SaveType::Manual);
+    }
+
+    #[test]
+    fn build_version() {
+        let version = BuildVersion(155350);
+        assert!(version.is_at_least(BuildVersion(152331)));
+        assert!(version.is_at_least(BuildVersion(155350)));
+        assert!(!version.is_at_least(BuildVersion(155351)));
+        assert!(version.is_tested());
+        assert!(!BuildVersion(100000).is_tested());
+        assert_eq!(BuildVersion::from(152331), BuildVersion::OLDEST_TESTED);
+        assert_eq!(version.to_string(), "155350");
+
+        let save_file = new_world();
+        assert_eq!(save_file.build_version(), BuildVersion::OLDEST_TESTED);
+        assert!(save_file.build_version().is_tested());
     }
 
     #[test]
@@ -355,6 +3909,394 @@ mod tests {
         assert_eq!(result.start_loc, "Grass Fields");
         assert_eq!(result.session_name, "test_file");
         assert_eq!(result.visibility, SessionVisiblity::SvPrivate);
+
+        let string = "? StartLoc =Grass%20Fields?SESSIONNAME=100%?visibility= SV_FriendsOnly";
+        let result = WorldProperties::parse(string).unwrap();
+        assert_eq!(result.start_loc, "Grass Fields");
+        assert_eq!(result.session_name, "100%");
+        assert_eq!(result.visibility, SessionVisiblity::SvFriendsOnly);
+
+        let data = std::fs::read("test_files/new_world.sav").unwrap();
+        let mut header = &data[12..];
+        read_string(&mut header).unwrap();
+        let string_start = data.len() - header.len();
+        let properties = WorldProperties::parse(&read_string(&mut header).unwrap()).unwrap();
+        let string_end = data.len() - header.len();
+        let mut written = Vec::new();
+        properties.write(&mut written).unwrap();
+        assert_eq!(written, &data[string_start..string_end]);
+
+        let properties = WorldProperties {
+            start_loc: "a?b".to_string(),
+            session_name: "100% %41".to_string(),
+            visibility: SvInvalid,
+        };
+        assert_eq!(
+            properties.to_string(),
+            "?startloc=a%3Fb?sessionName=100% %2541?Visibility=SV_Invalid"
+        );
+        assert_eq!(
+            WorldProperties::parse(&properties.to_string()).unwrap(),
+            properties
+        );
+
+        assert_eq!(percent_decode("%e3%81%82%2"), "あ%2");
+        assert_eq!(percent_decode("%+1"), "%+1");
+    }
+
+    #[test]
+    fn session_visibility_traits() {
+        for visibility in &[SvPrivate, SvFriendsOnly, SvInvalid] {
+            let parsed: SessionVisiblity = visibility.to_string().parse().unwrap();
+            assert_eq!(parsed, *visibility);
+        }
+        assert!("sv_private".parse::<SessionVisiblity>().is_err());
+
+        assert_eq!(SessionVisiblity::try_from(1).unwrap(), SvFriendsOnly);
+        let visibility: Result<SessionVisiblity> = 3_u8.try_into();
+        assert!(visibility.is_err());
+    }
+
+    #[test]
+    fn display() {
+        let vector = Vector3 {
+            x: 1.0,
+            y: -2.5,
+            z: 0.0,
+        };
+        assert_eq!(vector.to_string(), "(1, -2.5, 0)");
+
+        assert_eq!(SessionVisiblity::SvPrivate.to_string(), "SV_Private");
+        assert_eq!(
+            SessionVisiblity::SvFriendsOnly.to_string(),
+            "SV_FriendsOnly"
+        );
+        assert_eq!(SessionVisiblity::SvInvalid.to_string(), "SV_Invalid");
+
+        let component = SaveObject::new_component(
+            "/Script/FactoryGame.FGInventoryComponent",
+            "Persistent_Level:PersistentLevel.Char_Player_C_0.inventory",
+            "Persistent_Level:PersistentLevel.Char_Player_C_0",
+        );
+        assert_eq!(
+            component.to_string(),
+            "SaveComponent /Script/FactoryGame.FGInventoryComponent \
+            (Persistent_Level:PersistentLevel.Char_Player_C_0.inventory)"
+        );
+
+        let entity = SaveObject::SaveEntity {
+            type_path: "/Script/FactoryGame.FGFoliageRemoval".to_string(),
+            root_object: "Persistent_Level".to_string(),
+            instance_name: "Persistent_Level:PersistentLevel.FGFoliageRemoval_0".to_string(),
+            need_transform: false,
+            rotation: Default::default(),
+            position: Default::default(),
+            scale: Default::default(),
+            was_placed_in_level: true,
+        };
+        assert_eq!(
+            entity.to_string(),
+            "SaveEntity /Script/FactoryGame.FGFoliageRemoval \
+            (Persistent_Level:PersistentLevel.FGFoliageRemoval_0)"
+        );
+    }
+
+    #[test]
+    fn entity_bools() {
+        let entity_data = |need_transform: i32, was_placed_in_level: i32| -> Vec<u8> {
+            let mut data = 1_i32.to_le_bytes().to_vec();
+            for s in &["type", "root", "instance"] {
+                data.extend(to_encoding(s.as_bytes()));
+            }
+            data.extend_from_slice(&need_transform.to_le_bytes());
+            data.extend_from_slice(&[0; 40]);
+            data.extend_from_slice(&was_placed_in_level.to_le_bytes());
+            data
+        };
+
+        let object = SaveObject::parse(&mut entity_data(1, 0).as_slice()).unwrap();
+        let entity = object.as_entity().unwrap();
+        assert!(entity.need_transform());
+        assert!(!entity.was_placed_in_level());
+        assert!(entity.is_runtime_spawned());
+
+        let error = SaveObject::parse(&mut entity_data(2, 0).as_slice()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid bool: 2");
+        let error = SaveObject::parse(&mut entity_data(0, -1).as_slice()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid bool: -1");
+    }
+
+    /// Checks that every primitive read is little-endian, using hand-written byte encodings.
+    #[test]
+    fn little_endian() {
+        let mut header: Vec<u8> = Vec::new();
+        header.extend_from_slice(&[0x08, 0x00, 0x00, 0x00]); // save_header
+        header.extend_from_slice(&[0x19, 0x00, 0x00, 0x00]); // save_version
+        header.extend_from_slice(&[0x0B, 0x53, 0x02, 0x00]); // build_version
+        for s in &[
+            &b"Persistent_Level\0"[..],
+            &b"?startloc=a?sessionName=b?Visibility=SV_Private\0"[..],
+            &b"b\0"[..],
+        ] {
+            header.extend_from_slice(&[s.len() as u8, 0x00, 0x00, 0x00]);
+            header.extend_from_slice(s);
+        }
+        header.extend_from_slice(&[0x01, 0x02, 0x00, 0x00]); // play_time
+        header.extend_from_slice(&[0xF0, 0x49, 0xEE, 0x3D, 0xD7, 0x17, 0xD9, 0x08]); // save_date
+        header.push(0x01); // session_visibility
+        header.extend_from_slice(&[0x26, 0x00, 0x00, 0x00]); // editor_object_version
+        header.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // mod_meta_data
+        header.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]); // is_modded_save
+
+        let save_file = SaveFile::parse_header(&mut header.as_slice()).unwrap();
+        assert_eq!(save_file.save_header, 8);
+        assert_eq!(save_file.save_version, 25);
+        assert_eq!(save_file.build_version, 152331);
+        assert_eq!(save_file.world_type, "Persistent_Level");
+        assert_eq!(save_file.session_name, "b");
+        assert_eq!(save_file.play_time, Duration::seconds(0x0201));
+        assert_eq!(
+            save_file.save_date,
+            SaveFile::convert_date(0x08D9_17D7_3DEE_49F0)
+        );
+        assert_eq!(save_file.session_visibility, SvFriendsOnly);
+        assert_eq!(save_file.editor_object_version, 38);
+        assert!(save_file.is_modded_save);
+
+        // 1.0, -2.0, 0.5, 256.0
+        let floats: &[u8] = &[
+            0x00, 0x00, 0x80, 0x3F, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x3F, 0x00, 0x00,
+            0x80, 0x43,
+        ];
+        assert_eq!(
+            Vector3::parse(&mut &floats[..]).unwrap(),
+            Vector3 {
+                x: 1.0,
+                y: -2.0,
+                z: 0.5
+            }
+        );
+        assert_eq!(
+            Vector4::parse(&mut &floats[..]).unwrap(),
+            Vector4 {
+                x: 1.0,
+                y: -2.0,
+                z: 0.5,
+                w: 256.0
+            }
+        );
+
+        assert!(read_bool(&mut &[0x01, 0x00, 0x00, 0x00][..]).unwrap());
+        assert!(read_bool(&mut &[0x00, 0x00, 0x00, 0x01][..]).is_err());
+        assert_eq!(
+            read_string(&mut &[0x02, 0x00, 0x00, 0x00, b'a', 0x00][..]).unwrap(),
+            "a"
+        );
+        assert_eq!(
+            read_string(&mut &[0xFE, 0xFF, 0xFF, 0xFF, 0x42, 0x30, 0x00, 0x00][..]).unwrap(),
+            "\u{3042}"
+        );
+    }
+
+    #[test]
+    fn save_object_round_trip() {
+        let mut file = File::open("test_files/new_world.sav").unwrap();
+        let body = SaveFile::decompress_body(&mut file).unwrap();
+        let mut cursor = Cursor::new(body.as_slice());
+        let object_count = cursor.read_u32::<L>().unwrap();
+
+        let (mut entities, mut components) = (0, 0);
+        for _ in 0..object_count {
+            let start = cursor.position() as usize;
+            let object = SaveObject::parse(&mut cursor).unwrap();
+            let end = cursor.position() as usize;
+            match object {
+                SaveObject::SaveEntity { .. } => entities += 1,
+                SaveObject::SaveComponent { .. } => components += 1,
+            }
+
+            let mut written = Vec::new();
+            object.write(&mut written).unwrap();
+            assert_eq!(written, body[start..end], "{}", object.instance_name());
+        }
+        assert_eq!((entities, components), (13525, 395));
+    }
+
+    #[test]
+    fn vector_comparison() {
+        use std::cmp::Ordering;
+
+        let v = |x: f32, y: f32, z: f32| Vector3 { x, y, z };
+        let nan = v(f32::NAN, 0.0, 0.0);
+        assert_eq!(nan.partial_cmp(&v(1.0, 0.0, 0.0)), None);
+        assert_eq!(
+            nan.total_cmp(&v(f32::INFINITY, 0.0, 0.0)),
+            Ordering::Greater
+        );
+        assert_eq!(nan.total_cmp(&nan), Ordering::Equal);
+
+        let mut positions = [v(2.0, 0.0, 0.0), nan, v(1.0, 5.0, 0.0), v(1.0, 2.0, 0.0)];
+        positions.sort_by(Vector3::total_cmp);
+        assert_eq!(
+            positions[..3],
+            [v(1.0, 2.0, 0.0), v(1.0, 5.0, 0.0), v(2.0, 0.0, 0.0)]
+        );
+        assert!(positions[3].x.is_nan());
+
+        assert!(v(1.0, 2.0, 3.0).approx_eq(&v(1.05, 1.95, 3.0), 0.1));
+        assert!(!v(1.0, 2.0, 3.0).approx_eq(&v(1.2, 2.0, 3.0), 0.1));
+        assert!(!nan.approx_eq(&nan, f32::INFINITY));
+
+        let a = Vector2 { x: 1.0, y: 0.0 };
+        assert_eq!(a.total_cmp(&Vector2 { x: 1.0, y: -0.0 }), Ordering::Greater);
+        assert!(a.approx_eq(&Vector2 { x: 1.0, y: -0.0 }, 0.0));
+
+        let identity = Vector4 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        };
+        assert!(identity.approx_eq(&Vector4::from_axis_angle(v(0.0, 0.0, 1.0), 1e-4), 1e-3));
+        assert_eq!(identity.total_cmp(&identity), Ordering::Equal);
+    }
+
+    #[test]
+    fn vector_round_trip() {
+        // 1.0, -2.0, 0.5, 256.0
+        let floats: &[u8] = &[
+            0x00, 0x00, 0x80, 0x3F, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x3F, 0x00, 0x00,
+            0x80, 0x43,
+        ];
+
+        let mut written = Vec::new();
+        Vector2::parse(&mut &floats[..])
+            .unwrap()
+            .write(&mut written)
+            .unwrap();
+        assert_eq!(written, floats[..8]);
+
+        let mut written = Vec::new();
+        Vector3::parse(&mut &floats[..])
+            .unwrap()
+            .write(&mut written)
+            .unwrap();
+        assert_eq!(written, floats[..12]);
+
+        let mut written = Vec::new();
+        Vector4::parse(&mut &floats[..])
+            .unwrap()
+            .write(&mut written)
+            .unwrap();
+        assert_eq!(written, floats);
+    }
+
+    #[test]
+    fn transform() {
+        let data: Vec<u8> = (1..=10)
+            .flat_map(|n| (n as f32).to_le_bytes().to_vec())
+            .collect();
+        let transform = Transform::parse(&mut data.as_slice()).unwrap();
+        assert_eq!(
+            transform.rotation,
+            Vector4 {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+                w: 4.0
+            }
+        );
+        assert_eq!(
+            transform.translation,
+            Vector3 {
+                x: 5.0,
+                y: 6.0,
+                z: 7.0
+            }
+        );
+        assert_eq!(
+            transform.scale,
+            Vector3 {
+                x: 8.0,
+                y: 9.0,
+                z: 10.0
+            }
+        );
+    }
+
+    #[test]
+    fn object_reference() {
+        let reference = ObjectReference {
+            level_name: "Persistent_Level".to_string(),
+            path_name: "path".to_string(),
+        };
+
+        let mut pair = Vec::new();
+        reference.write_pair(&mut pair).unwrap();
+        assert_eq!(pair.len(), (4 + 17) + (4 + 5));
+        let mut data = pair.as_slice();
+        assert_eq!(ObjectReference::parse_pair(&mut data).unwrap(), reference);
+        assert!(data.is_empty());
+
+        let mut single = Vec::new();
+        reference.write_single(&mut single).unwrap();
+        assert_eq!(single.len(), 4 + 5);
+        let mut data = single.as_slice();
+        assert_eq!(
+            ObjectReference::parse_single(&mut data).unwrap(),
+            ObjectReference {
+                level_name: String::new(),
+                path_name: "path".to_string(),
+            }
+        );
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn color() {
+        let color = Color::parse(&mut &[1_u8, 2, 3, 4][..]).unwrap();
+        assert_eq!(
+            color,
+            Color {
+                r: 3,
+                g: 2,
+                b: 1,
+                a: 4
+            }
+        );
+
+        for n in 0..=255 {
+            let color = Color {
+                r: n,
+                g: 255 - n,
+                b: n / 2,
+                a: n,
+            };
+            assert_eq!(Color::from(LinearColor::from(color)), color);
+        }
+
+        let white = LinearColor::from(Color {
+            r: 255,
+            g: 255,
+            b: 255,
+            a: 255,
+        });
+        assert_eq!(
+            white,
+            LinearColor {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+                a: 1.0
+            }
+        );
+        let clamped = Color::from(LinearColor {
+            r: 2.0,
+            g: -1.0,
+            b: 0.5,
+            a: 0.5,
+        });
+        assert_eq!((clamped.r, clamped.g, clamped.a), (255, 0, 128));
     }
 
     fn to_encoding(b: &[u8]) -> Vec<u8> {
@@ -389,27 +4331,130 @@ mod tests {
             assert!(read_string(&mut &data.to_vec()[..]).is_err());
         }
 
+        // Boundary lengths. Each case must be read to the end.
+        let cases: &[(i32, &[u8], &str)] = &[
+            (0, &[], ""),
+            (1, &[0], ""),
+            (2, &[b'a', 0], "a"),
+            (-1, &[0, 0], ""),
+            (-2, &[b'a', 0, 0, 0], "a"),
+        ];
+        for (length, bytes, expected) in cases {
+            let encoded: Vec<u8> = length.to_le_bytes().iter().chain(*bytes).copied().collect();
+            let mut data = encoded.as_slice();
+            assert_eq!(read_string(&mut data).unwrap(), *expected, "{}", length);
+            assert!(data.is_empty(), "{}", length);
+        }
+
         // Various strings
         for test_string in &["", "a", "abc"] {
             let encoded = to_encoding(test_string.as_bytes());
-            assert_eq!(read_string(&mut encoded.as_slice()).unwrap(), *test_string);
+            let mut data = encoded.as_slice();
+            assert_eq!(read_string(&mut data).unwrap(), *test_string);
+            assert!(data.is_empty());
+
+            let mut written = Vec::new();
+            write_string(&mut written, test_string).unwrap();
+            if test_string.is_empty() {
+                assert_eq!(written, 0_i32.to_le_bytes());
+            } else {
+                assert_eq!(written, encoded);
+            }
         }
         {
-            // UTF-16
-            let test_string = "abc";
+            // UTF-16. The length is the number of code units including the terminator.
+            let test_string = "aé😀";
             let utf16: Vec<u16> = test_string.encode_utf16().collect();
             let mut utf16_bytes: Vec<u8> = Vec::new();
-            for n in utf16 {
+            for n in &utf16 {
                 utf16_bytes.extend_from_slice(&n.to_le_bytes());
             }
-            let encoded: Vec<u8> = (-(utf16_bytes.len() as i32 + 2))
+            let encoded: Vec<u8> = (-(utf16.len() as i32 + 1))
                 .to_le_bytes()
                 .iter()
                 .chain(utf16_bytes.iter())
                 .chain([b'\0', b'\0'].iter())
                 .copied()
                 .collect();
-            assert_eq!(read_string(&mut encoded.as_slice()).unwrap(), test_string);
+            let mut data = encoded.as_slice();
+            assert_eq!(read_string(&mut data).unwrap(), test_string);
+            assert!(data.is_empty());
+
+            let mut written = Vec::new();
+            write_string(&mut written, test_string).unwrap();
+            assert_eq!(written, encoded);
+        }
+    }
+
+    #[test]
+    fn invalid_utf16_index() {
+        assert_eq!(super::invalid_utf16_index(&[]), None);
+        let valid: Vec<u16> = "aé😀".encode_utf16().collect();
+        assert_eq!(super::invalid_utf16_index(&valid), None);
+        // Unpaired high surrogate after "a😀"
+        assert_eq!(
+            super::invalid_utf16_index(&[0x61, 0xD83D, 0xDE00, 0xD83D, 0x61]),
+            Some(3)
+        );
+        assert_eq!(super::invalid_utf16_index(&[0xDE00]), Some(0));
+
+        // Decoding is still lossy
+        let mut data = Vec::new();
+        data.extend_from_slice(&(-2_i32).to_le_bytes());
+        data.extend_from_slice(&[0x00, 0xDE, 0x00, 0x00]);
+        assert_eq!(read_string(&mut data.as_slice()).unwrap(), "\u{FFFD}");
+        let data = [3, 0, 0, 0, 0xff, b'a', 0];
+        assert_eq!(read_string(&mut &data[..]).unwrap(), "\u{FFFD}a");
+    }
+
+    #[test]
+    fn string_encoding() {
+        let cases: &[(i32, &[u8], &str, StringEncoding)] = &[
+            (0, &[], "", StringEncoding::Empty),
+            (1, &[0], "", StringEncoding::Utf8),
+            (-1, &[0, 0], "", StringEncoding::Utf16),
+            (2, &[b'a', 0], "a", StringEncoding::Utf8),
+            // ASCII stored as UTF-16, which write_string() would write as bytes
+            (-2, &[b'a', 0, 0, 0], "a", StringEncoding::Utf16),
+            (3, &[0xc3, 0xa9, 0], "é", StringEncoding::Utf8),
+        ];
+        for (length, bytes, expected, expected_encoding) in cases {
+            let encoded: Vec<u8> = length.to_le_bytes().iter().chain(*bytes).copied().collect();
+            let (s, encoding) = read_string_with_encoding(&mut encoded.as_slice()).unwrap();
+            assert_eq!(s, *expected, "{}", length);
+            assert_eq!(encoding, *expected_encoding, "{}", length);
+
+            let mut written = Vec::new();
+            write_string_with_encoding(&mut written, &s, encoding).unwrap();
+            assert_eq!(written, encoded, "{}", length);
         }
+
+        assert_eq!(StringEncoding::of(""), StringEncoding::Empty);
+        assert_eq!(StringEncoding::of("a"), StringEncoding::Utf8);
+        assert_eq!(StringEncoding::of("é"), StringEncoding::Utf16);
+        assert!(write_string_with_encoding(&mut Vec::new(), "a", StringEncoding::Empty).is_err());
+    }
+
+    #[test]
+    fn write_string_too_long() {
+        for (s, encoding) in [
+            ("a".repeat(MAX_STRING_LENGTH - 1), StringEncoding::Utf8),
+            ("é".repeat(MAX_STRING_LENGTH - 1), StringEncoding::Utf16),
+        ] {
+            let mut written = Vec::new();
+            write_string_with_encoding(&mut written, &s, encoding).unwrap();
+            assert_eq!(read_string(&mut written.as_slice()).unwrap(), s);
+
+            let s = format!("{}{}", s, s.chars().next().unwrap());
+            match write_string_with_encoding(&mut Vec::new(), &s, encoding).unwrap_err() {
+                ParseError::Io(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+                e => panic!("unexpected error: {:?}", e),
+            }
+        }
+
+        let save_file = SaveFileBuilder::new()
+            .session_name(&"a".repeat(MAX_STRING_LENGTH))
+            .build();
+        assert!(save_file.write(&mut Cursor::new(Vec::new())).is_err());
     }
 }
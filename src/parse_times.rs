@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Cumulative time spent parsing objects of each `type_path`. Enabled by the `trace` feature.
+#[derive(Debug, Default)]
+pub(crate) struct ParseTimes {
+    times: HashMap<String, Duration>,
+}
+
+impl ParseTimes {
+    pub(crate) fn add(&mut self, type_path: &str, duration: Duration) {
+        match self.times.get_mut(type_path) {
+            Some(total) => *total += duration,
+            None => {
+                self.times.insert(type_path.to_string(), duration);
+            }
+        }
+    }
+
+    /// Logs the totals at debug level, slowest first.
+    pub(crate) fn log(&self) {
+        let mut times: Vec<(&String, &Duration)> = self.times.iter().collect();
+        times.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        log::debug!("object parse time by type_path:");
+        for (type_path, duration) in times {
+            log::debug!("{:?} {}", duration, type_path);
+        }
+    }
+}
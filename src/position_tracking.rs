@@ -0,0 +1,31 @@
+use std::io::Read;
+
+/// Counts the number of bytes read from the inner reader so that errors can report where they
+/// occurred.
+#[derive(Debug)]
+pub(crate) struct PositionTracking<R>
+where
+    R: Read,
+{
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> PositionTracking<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    /// Number of bytes read so far.
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<R: Read> Read for PositionTracking<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
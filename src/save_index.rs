@@ -0,0 +1,68 @@
+use super::*;
+
+/// Byte offsets of the objects in a decompressed body, for reading single objects on demand. Build
+/// it from the output of `SaveFile::decompress_body()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SaveIndex {
+    offsets: HashMap<String, u64>,
+}
+
+impl SaveIndex {
+    /// Reads the object headers in `body` and records where each one starts. Object data and
+    /// collected objects are not read.
+    pub fn build(body: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(body);
+        let object_count = cursor.read_u32::<L>()?;
+        let mut offsets = HashMap::new();
+        for _ in 0..object_count {
+            let offset = cursor.position();
+            let object = SaveObject::parse(&mut cursor).map_err(|source| ParseError::At {
+                section: Section::Body,
+                position: cursor.position(),
+                source: Box::new(source),
+            })?;
+            offsets.insert(object.instance_name().to_string(), offset);
+        }
+        Ok(Self { offsets })
+    }
+
+    /// Offset of the object named `instance_name` from the start of the body.
+    pub fn offset(&self, instance_name: &str) -> Option<u64> {
+        self.offsets.get(instance_name).copied()
+    }
+
+    /// Reads the object named `instance_name` from `body`, which must be the buffer this index was
+    /// built from.
+    pub fn read_object(&self, body: &[u8], instance_name: &str) -> Result<SaveObject> {
+        let offset = self
+            .offset(instance_name)
+            .ok_or_else(|| ParseError::ObjectNotFound(instance_name.to_string()))?;
+        let mut cursor = Cursor::new(body);
+        cursor.set_position(offset);
+        SaveObject::parse(&mut cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn read_object() {
+        let body = SaveFile::decompress_body(&mut File::open("test_files/new_world.sav").unwrap())
+            .unwrap();
+        assert_eq!(body.len(), 7514008);
+
+        let index = SaveIndex::build(&body).unwrap();
+        assert_eq!(index.offset("missing"), None);
+        assert!(index.read_object(&body, "missing").is_err());
+
+        for object in crate::tests::new_world().save_objects.iter().step_by(1000) {
+            assert_eq!(
+                &index.read_object(&body, object.instance_name()).unwrap(),
+                object
+            );
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use super::*;
+use crate::zlib_writer::{write_chunk, ChunkedZLibWriter};
+
+/// Writes a save file one object at a time, without holding all objects in memory.
+///
+/// Call `write_header()`, then `write_object()` for each object, then `write_object_data()` for
+/// each object in the same order, then `finish()`. The methods panic if they are called out of
+/// that order.
+///
+/// The body starts with its length and the object count, which are not known until everything has
+/// been written. They are written in a first chunk of their own that is stored without
+/// compression, so that `finish()` can seek back and rewrite the chunk at the same size.
+#[derive(Debug)]
+pub struct SaveWriter<W>
+where
+    W: Write + Seek,
+{
+    file: Option<W>,
+    body: Option<ChunkedZLibWriter<W>>,
+    level: Compression,
+    /// Position of the first chunk, which holds the body length and the object count.
+    first_chunk_position: u64,
+    /// Number of bytes written after the body length prefix.
+    body_length: u64,
+    object_count: u32,
+    object_data_count: Option<u32>,
+}
+
+impl<W: Write + Seek> SaveWriter<W> {
+    pub fn new(file: W) -> Self {
+        SaveWriter::with_level(file, Compression::default())
+    }
+
+    /// Compresses the body with `level` instead of the default level, which is the level the game
+    /// uses.
+    pub fn with_level(file: W, level: Compression) -> Self {
+        Self {
+            file: Some(file),
+            body: None,
+            level,
+            first_chunk_position: 0,
+            body_length: 0,
+            object_count: 0,
+            object_data_count: None,
+        }
+    }
+
+    /// Writes the header fields of `save_file` and starts the body.
+    pub fn write_header(&mut self, save_file: &SaveFile) -> Result<()> {
+        let mut file = self.file.take().expect("write_header() was already called");
+        write_header(&mut file, save_file)?;
+
+        self.first_chunk_position = file.stream_position()?;
+        write_first_chunk(&mut file, 0, 0)?;
+        self.body = Some(ChunkedZLibWriter::with_level(file, self.level));
+        self.body_length = 4; // Object count
+        Ok(())
+    }
+
+    /// Writes the header of the next object.
+    pub fn write_object(&mut self, object: &SaveObject) -> Result<()> {
+        assert!(
+            self.object_data_count.is_none(),
+            "write_object() was called after write_object_data()"
+        );
+        let mut buffer = Vec::new();
+        object.write(&mut buffer)?;
+        self.write_body(&buffer)?;
+        self.object_count = self.object_count.checked_add(1).ok_or_else(too_long)?;
+        Ok(())
+    }
+
+    /// Writes the data of the next object. Object data must be written in the same order as the
+    /// objects.
+    pub fn write_object_data(&mut self, data: &[u8]) -> Result<()> {
+        self.start_object_data()?;
+        let length: i32 = data.len().try_into().map_err(|_| too_long())?;
+        self.write_body(&length.to_le_bytes())?;
+        self.write_body(data)?;
+        *self
+            .object_data_count
+            .as_mut()
+            .expect("start_object_data() was not called") += 1;
+        Ok(())
+    }
+
+    /// Writes `collected_objects`, compresses the last chunk, and fills in the body length and the
+    /// object count. Returns an error if the number of object data blobs does not match the number
+    /// of objects.
+    pub fn finish(mut self, collected_objects: &[ObjectReference]) -> Result<W> {
+        self.start_object_data()?;
+        let object_data_count = self
+            .object_data_count
+            .expect("start_object_data() was not called");
+        if object_data_count != self.object_count {
+            return Err(ParseError::ObjectDataCountMismatch {
+                object_data_count,
+                object_count: self.object_count,
+            });
+        }
+
+        let mut buffer = Vec::new();
+        let collected_object_count: u32 =
+            collected_objects.len().try_into().map_err(|_| too_long())?;
+        buffer.write_u32::<L>(collected_object_count)?;
+        for reference in collected_objects {
+            reference.write_pair(&mut buffer)?;
+        }
+        self.write_body(&buffer)?;
+
+        let mut file = self
+            .body
+            .take()
+            .expect("write_header() was not called")
+            .finish()?;
+        let body_length: i32 = self.body_length.try_into().map_err(|_| too_long())?;
+        file.seek(SeekFrom::Start(self.first_chunk_position))?;
+        write_first_chunk(&mut file, body_length, self.object_count)?;
+        file.seek(SeekFrom::End(0))?;
+        file.flush()?;
+        Ok(file)
+    }
+
+    /// Writes the object data count when the first object data is written.
+    fn start_object_data(&mut self) -> Result<()> {
+        if self.object_data_count.is_none() {
+            self.write_body(&self.object_count.to_le_bytes())?;
+            self.object_data_count = Some(0);
+        }
+        Ok(())
+    }
+
+    fn write_body(&mut self, data: &[u8]) -> Result<()> {
+        self.body
+            .as_mut()
+            .expect("write_header() was not called")
+            .write_all(data)?;
+        self.body_length += data.len() as u64;
+        Ok(())
+    }
+}
+
+fn too_long() -> ParseError {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, "save file is too long").into()
+}
+
+/// Writes the header fields in the same order as `SaveFile::read_header()`.
+fn write_header<W>(file: &mut W, save_file: &SaveFile) -> Result<()>
+where
+    W: Write,
+{
+    let play_time: i32 = save_file
+        .play_time
+        .num_seconds()
+        .try_into()
+        .map_err(|_| ParseError::InvalidPlayTime(save_file.play_time))?;
+    let save_date = SaveFile::date_to_ticks(save_file.save_date).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "save date is out of range",
+        )
+    })?;
+
+    file.write_i32::<L>(save_file.save_header)?;
+    file.write_i32::<L>(save_file.save_version)?;
+    file.write_i32::<L>(save_file.build_version)?;
+    write_string(file, &save_file.world_type)?;
+    save_file.world_properties.write(file)?;
+    write_string(file, &save_file.session_name)?;
+    file.write_i32::<L>(play_time)?;
+    file.write_i64::<L>(save_date)?;
+    file.write_u8(save_file.session_visibility.to_u8())?;
+    file.write_i32::<L>(save_file.editor_object_version)?;
+    write_string(file, &save_file.mod_meta_data)?;
+    file.write_i32::<L>(save_file.is_modded_save.into())?;
+    Ok(())
+}
+
+/// Writes the chunk that holds the body length and the object count. It is stored without
+/// compression so that its size does not depend on the values.
+fn write_first_chunk<W>(file: &mut W, body_length: i32, object_count: u32) -> Result<()>
+where
+    W: Write,
+{
+    let mut data = Vec::new();
+    data.write_i32::<L>(body_length)?;
+    data.write_u32::<L>(object_count)?;
+    write_chunk(
+        file,
+        &data,
+        ChunkedZLibWriter::<W>::DEFAULT_CHUNK_SIZE,
+        Compression::none(),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::new_world;
+
+    #[test]
+    fn round_trip() {
+        let save_file = new_world().clone();
+
+        let mut data = Cursor::new(Vec::new());
+        save_file.write(&mut data).unwrap();
+        data.set_position(0);
+        let written = SaveFile::parse(&mut data).unwrap();
+        assert!(written.structurally_eq(&save_file));
+        assert_eq!(written.play_time, save_file.play_time);
+        assert_eq!(written.save_date, save_file.save_date);
+        assert_eq!(
+            written,
+            SaveFile {
+                compressed_size: written.compressed_size,
+                ..save_file
+            }
+        );
+    }
+
+    #[test]
+    fn level() {
+        let save_file = new_world();
+
+        let mut sizes = Vec::new();
+        for compression in [Compression::fast(), Compression::best()] {
+            let mut data = Cursor::new(Vec::new());
+            save_file
+                .write_with_options(&mut data, &WriteOptions { compression })
+                .unwrap();
+            sizes.push(data.get_ref().len());
+            data.set_position(0);
+            assert!(SaveFile::parse(&mut data)
+                .unwrap()
+                .structurally_eq(save_file));
+        }
+        assert!(sizes[0] > sizes[1]);
+    }
+
+    #[test]
+    fn object_data_count_mismatch() {
+        let mut writer = SaveWriter::new(Cursor::new(Vec::new()));
+        writer.write_header(&SaveFile::default()).unwrap();
+        writer
+            .write_object(&SaveObject::new_component("type", "component", ""))
+            .unwrap();
+        assert!(matches!(
+            writer.finish(&[]).unwrap_err(),
+            ParseError::ObjectDataCountMismatch {
+                object_data_count: 0,
+                object_count: 1,
+            }
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "write_header() was not called")]
+    fn finish_without_header() {
+        let writer = SaveWriter::new(Cursor::new(Vec::new()));
+        let _ = writer.finish(&[]);
+    }
+
+    #[test]
+    fn empty() {
+        let mut writer = SaveWriter::new(Cursor::new(Vec::new()));
+        let save_file = SaveFileBuilder::new()
+            .session_name("empty")
+            .visibility(SessionVisiblity::SvFriendsOnly)
+            .build();
+        writer.write_header(&save_file).unwrap();
+        let reference = ObjectReference {
+            level_name: "Persistent_Level".to_string(),
+            path_name: "collected".to_string(),
+        };
+        let mut data = writer.finish(std::slice::from_ref(&reference)).unwrap();
+
+        data.set_position(0);
+        let written = SaveFile::parse(&mut data).unwrap();
+        assert!(written.structurally_eq(&SaveFile {
+            collected_objects: vec![reference],
+            ..save_file
+        }));
+    }
+}
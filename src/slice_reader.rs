@@ -0,0 +1,124 @@
+use super::*;
+
+/// Reads from a byte slice, checking every read against the remaining input.
+///
+/// Use `parse()` to run a parse function on the slice. A read past the end of the slice returns
+/// `ParseError::UnexpectedEof` instead of an I/O error. Reads copy straight out of the slice without
+/// an intermediate buffer, but parse functions still allocate the values they return, such as the
+/// `String`s of an object header.
+#[derive(Debug)]
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Size of the read that went past the end of the slice.
+    eof: Option<usize>,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            eof: None,
+        }
+    }
+
+    /// Number of bytes read so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Number of bytes that have not been read yet.
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    /// Runs `f` on this reader. Returns `ParseError::UnexpectedEof` if `f` failed because it read
+    /// past the end of the slice.
+    ///
+    /// ```
+    /// use satisfactory_save_file::{read_string, slice_reader::SliceReader};
+    ///
+    /// let mut reader = SliceReader::new(b"\x04\x00\x00\x00abc");
+    /// assert!(reader.parse(read_string).is_err());
+    /// ```
+    pub fn parse<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        self.eof = None;
+        f(self).map_err(|e| match (e, self.eof) {
+            (ParseError::Io(e), Some(needed)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                ParseError::UnexpectedEof {
+                    needed,
+                    remaining: self.remaining(),
+                }
+            }
+            (e, _) => e,
+        })
+    }
+}
+
+impl Read for SliceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let length = buf.len().min(self.remaining());
+        buf[..length].copy_from_slice(&self.data[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        if buf.len() > self.remaining() {
+            self.eof = Some(buf.len());
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        self.read(buf).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated() {
+        let object = SaveObject::SaveComponent {
+            type_path: "type".to_string(),
+            root_object: "Persistent_Level".to_string(),
+            instance_name: "component".to_string(),
+            parent_entity_name: "parent".to_string(),
+        };
+        let mut data = Vec::new();
+        object.write(&mut data).unwrap();
+
+        let mut reader = SliceReader::new(&data);
+        assert_eq!(reader.parse(SaveObject::parse).unwrap(), object);
+        assert_eq!(reader.remaining(), 0);
+
+        for length in 0..data.len() {
+            let mut reader = SliceReader::new(&data[..length]);
+            assert!(matches!(
+                reader.parse(SaveObject::parse).unwrap_err(),
+                ParseError::UnexpectedEof { .. }
+            ));
+        }
+
+        let mut reader = SliceReader::new(&data[..10]);
+        assert!(matches!(
+            reader.parse(SaveObject::parse).unwrap_err(),
+            ParseError::UnexpectedEof {
+                needed: 5,
+                remaining: 2,
+            }
+        ));
+    }
+
+    #[test]
+    fn huge_string_length() {
+        let mut reader = SliceReader::new(&[0xff, 0xff, 0xff, 0x7f]);
+        assert!(matches!(
+            reader.parse(read_string).unwrap_err(),
+            ParseError::InvalidStringLength(_)
+        ));
+    }
+}
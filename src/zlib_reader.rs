@@ -2,6 +2,9 @@ use super::*;
 use flate2::read::ZlibDecoder;
 use std::io::Take;
 
+/// Tag at the start of each chunk header.
+pub(crate) const PACKAGE_FILE_TAG: i64 = 0x9E2A83C1;
+
 /// Reads the zlib compressed parts of the file.
 #[derive(Debug)]
 pub struct ChunkedZLibReader<R>
@@ -9,80 +12,349 @@ where
     R: Read,
 {
     decoder: Option<ZlibDecoder<Take<R>>>,
+    body_length: u64,
+    max_chunk_size: u64,
+    chunk_count: usize,
+    compressed_size: u64,
+    decompressed_size: u64,
+    /// Declared uncompressed length of the current chunk.
+    chunk_length: u64,
+    /// Number of bytes decompressed from the current chunk, including the body length prefix.
+    chunk_decompressed_size: u64,
+    skip_bad_chunks: bool,
+    skipped_chunks: Vec<SkippedChunk>,
+    /// Number of zeros that remain to be returned in place of a skipped chunk.
+    zero_fill: u64,
+}
+
+/// Chunk that failed to decompress and whose remaining bytes were replaced with zeros.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SkippedChunk {
+    /// Index of the chunk, starting at 0.
+    pub index: usize,
+    /// Offset in the body where the zeros start, not counting the body length prefix.
+    pub body_offset: u64,
+    /// Number of zeros, which is the rest of the chunk's declared uncompressed length.
+    pub length: u64,
 }
 
 impl<R: Read> ChunkedZLibReader<R> {
     pub fn new(mut file: R) -> Result<Self> {
-        let chunk_length = ChunkedZLibReader::read_header(&mut file)?;
-        let mut decoder = ZlibDecoder::new(file.take(chunk_length));
+        let header = ChunkHeader::parse(&mut file)?;
+        let mut decoder = ZlibDecoder::new(file.take(header.compressed_length));
 
-        // Data length
-        decoder.read_i32::<L>()?;
+        let body_length = decoder.read_i32::<L>()?;
+        let body_length = body_length
+            .try_into()
+            .map_err(|_| ParseError::InvalidBodyLength(body_length))?;
 
         Ok(Self {
             decoder: Some(decoder),
+            body_length,
+            max_chunk_size: header.max_chunk_size,
+            chunk_count: 1,
+            compressed_size: header.compressed_length,
+            decompressed_size: 0,
+            chunk_length: header.uncompressed_length,
+            chunk_decompressed_size: 4,
+            skip_bad_chunks: false,
+            skipped_chunks: Vec::new(),
+            zero_fill: 0,
         })
     }
 
-    fn read_header(file: &mut R) -> Result<u64> {
+    /// If true, a chunk that fails to decompress is skipped using its compressed length, and the
+    /// rest of its declared uncompressed length is read as zeros so that later offsets stay the
+    /// same. The default is false, which returns the decompression error.
+    ///
+    /// The zeros are not valid save data, so `SaveFile` does not use this. A caller that decodes
+    /// the body must stop at the `body_offset` of the first entry in `skipped_chunks()`.
+    pub fn set_skip_bad_chunks(&mut self, skip_bad_chunks: bool) {
+        self.skip_bad_chunks = skip_bad_chunks;
+    }
+
+    /// Chunks that were skipped because they failed to decompress.
+    pub fn skipped_chunks(&self) -> &[SkippedChunk] {
+        &self.skipped_chunks
+    }
+
+    /// Length of the decompressed body as declared by the prefix at the start of the body. `read()`
+    /// reports end of file after this many bytes, so `read_exact()` past it returns an error.
+    pub fn body_length(&self) -> u64 {
+        self.body_length
+    }
+
+    /// Number of body bytes that have not been read yet.
+    pub fn remaining_body_length(&self) -> u64 {
+        self.body_length.saturating_sub(self.decompressed_size)
+    }
+
+    /// Maximum uncompressed chunk size declared by the most recent chunk header.
+    pub fn max_chunk_size(&self) -> u64 {
+        self.max_chunk_size
+    }
+
+    /// Number of chunks that have been started so far.
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_count
+    }
+
+    /// Total compressed length of the chunks that have been started so far.
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// Total number of decompressed bytes returned by `read()`, not counting the data length
+    /// prefix.
+    pub fn decompressed_size(&self) -> u64 {
+        self.decompressed_size
+    }
+
+    /// Reads the next chunk header from `file` and starts decompressing the chunk. Returns false at
+    /// end of file.
+    fn next_chunk(&mut self, mut file: R) -> std::io::Result<bool> {
+        let header = match ChunkHeader::parse(&mut file) {
+            Ok(header) => header,
+            // If end of file is reached, attempting to read header returns UnexpectedEof
+            Err(ParseError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(false)
+            }
+            Err(e) => return Err(std::io::Error::other(e)),
+        };
+
+        self.decoder = Some(ZlibDecoder::new(file.take(header.compressed_length)));
+        self.max_chunk_size = header.max_chunk_size;
+        self.chunk_count += 1;
+        self.compressed_size += header.compressed_length;
+        self.chunk_length = header.uncompressed_length;
+        self.chunk_decompressed_size = 0;
+        Ok(true)
+    }
+
+    /// Skips the rest of the current chunk and queues zeros in place of its missing bytes.
+    fn skip_chunk(&mut self) -> std::io::Result<()> {
+        let mut compressed = self.decoder.take().unwrap().into_inner();
+        std::io::copy(&mut compressed, &mut std::io::sink())?;
+
+        let length = self
+            .chunk_length
+            .saturating_sub(self.chunk_decompressed_size);
+        log::warn!(
+            "skipped chunk {} at body offset {}",
+            self.chunk_count - 1,
+            self.decompressed_size
+        );
+        self.skipped_chunks.push(SkippedChunk {
+            index: self.chunk_count - 1,
+            body_offset: self.decompressed_size,
+            length,
+        });
+        self.zero_fill = length;
+        self.next_chunk(compressed.into_inner())?;
+        Ok(())
+    }
+
+    fn add_decompressed(&mut self, bytes_read: usize) {
+        self.decompressed_size += bytes_read as u64;
+        self.chunk_decompressed_size += bytes_read as u64;
+    }
+}
+
+/// Length of the header that precedes each compressed chunk.
+pub(crate) const CHUNK_HEADER_LENGTH: u64 = 48;
+
+/// Reads the chunk headers from the current position up to `end` without decompressing anything.
+/// Returns the position where the last chunk ends, which is past `end` if the last chunk is cut
+/// short, and the total uncompressed length of the chunks.
+pub(crate) fn chunk_extent<R>(file: &mut R, end: u64) -> Result<(u64, u64)>
+where
+    R: Read + Seek,
+{
+    let mut position = file.stream_position()?;
+    let mut uncompressed_length = 0;
+    while position < end {
+        if end - position < CHUNK_HEADER_LENGTH {
+            return Ok((position + CHUNK_HEADER_LENGTH, uncompressed_length));
+        }
+        let header = ChunkHeader::parse(file)?;
+        position += CHUNK_HEADER_LENGTH + header.compressed_length;
+        uncompressed_length += header.uncompressed_length;
+        file.seek(SeekFrom::Start(position))?;
+    }
+    Ok((position, uncompressed_length))
+}
+
+/// Header that precedes each compressed chunk.
+struct ChunkHeader {
+    max_chunk_size: u64,
+    compressed_length: u64,
+    uncompressed_length: u64,
+}
+
+impl ChunkHeader {
+    /// Reads a chunk header. Chunks may use any maximum chunk size, but a chunk whose uncompressed
+    /// length exceeds its declared maximum is an error.
+    fn parse<R>(file: &mut R) -> Result<Self>
+    where
+        R: Read,
+    {
         let package_file_tag = file.read_i64::<L>()?;
-        if package_file_tag != 0x9E2A83C1 {
+        if package_file_tag != PACKAGE_FILE_TAG {
             log::error!("unexpected package file tag: {}", package_file_tag);
         }
-        let max_chunk_size = file.read_i64::<L>()?;
-        if max_chunk_size != 0x20000 {
-            log::error!("unexpected max chunk size {}", max_chunk_size);
+        let max_chunk_size = read_length(file)?;
+        let compressed_length = read_length(file)?;
+        let uncompressed_length = read_length(file)?;
+        if uncompressed_length > max_chunk_size {
+            return Err(ParseError::ChunkTooLarge {
+                length: uncompressed_length,
+                max_chunk_size,
+            });
         }
 
-        let chunk_compressed_length = file.read_i64::<L>()?;
-        // Uncompressed length
-        file.read_i64::<L>()?;
-
         // Duplicate of compressed and uncompressed lengths
         file.read_i64::<L>()?;
         file.read_i64::<L>()?;
 
-        Ok(chunk_compressed_length.try_into()?)
+        Ok(Self {
+            max_chunk_size,
+            compressed_length,
+            uncompressed_length,
+        })
     }
 }
 
+fn read_length<R>(file: &mut R) -> Result<u64>
+where
+    R: Read,
+{
+    let n = file.read_i64::<L>()?;
+    n.try_into().map_err(|_| ParseError::InvalidChunkLength(n))
+}
+
 impl<R: Read> Read for ChunkedZLibReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let result = if let Some(decoder) = self.decoder.as_mut() {
-            decoder.read(buf)
-        } else {
-            // This branch happens after read_header() returned UnexpectedEof below. We return 0 to
-            // indicate end of file.
+        // The end of the body is the end of the stream, even if the chunks hold more data
+        let remaining = self.remaining_body_length();
+        let length = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let buf = &mut buf[..length];
+        if buf.is_empty() {
             return Ok(0);
-        };
+        }
 
-        if let Ok(bytes_read) = result {
-            // End of chunk
-            if bytes_read < buf.len() {
-                let mut file = self.decoder.take().unwrap().into_inner().into_inner();
-
-                let chunk_length = match ChunkedZLibReader::read_header(&mut file) {
-                    Ok(n) => n,
-                    Err(e) => {
-                        if let Some(e) = e.downcast_ref::<std::io::Error>() {
-                            if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                                // If end of file is reached, attempting to read header returns
-                                // UnexpectedEof
-                                return Ok(bytes_read);
-                            }
-                        }
-                        return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
-                    }
-                };
+        if self.zero_fill > 0 {
+            let length = buf
+                .len()
+                .min(self.zero_fill.try_into().unwrap_or(usize::MAX));
+            buf[..length].fill(0);
+            self.zero_fill -= length as u64;
+            self.decompressed_size += length as u64;
+            return Ok(length);
+        }
 
-                self.decoder = Some(ZlibDecoder::new(file.take(chunk_length)));
+        loop {
+            let result = if let Some(decoder) = self.decoder.as_mut() {
+                decoder.read(buf)
+            } else {
+                // This branch happens after next_chunk() reached end of file. We return 0 to
+                // indicate end of file.
+                return Ok(0);
+            };
 
-                if bytes_read == 0 {
-                    return self.decoder.as_mut().unwrap().read(buf);
+            match result {
+                // The decoder may return fewer bytes than requested in the middle of a chunk, for
+                // example when the inner reader returns short reads. Only 0 means the end of the
+                // chunk.
+                Ok(0) => {
+                    let mut compressed = self.decoder.take().unwrap().into_inner();
+                    // Skip anything after the end of the zlib stream
+                    std::io::copy(&mut compressed, &mut std::io::sink())?;
+                    if !self.next_chunk(compressed.into_inner())? {
+                        return Ok(0);
+                    }
+                }
+                Ok(bytes_read) => {
+                    self.add_decompressed(bytes_read);
+                    return Ok(bytes_read);
+                }
+                Err(e)
+                    if self.skip_bad_chunks
+                        && matches!(
+                            e.kind(),
+                            std::io::ErrorKind::InvalidInput | std::io::ErrorKind::InvalidData
+                        ) =>
+                {
+                    self.skip_chunk()?;
+                    return self.read(buf);
                 }
+                Err(e) => return Err(e),
             }
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn body_length() {
+        let mut file = File::open("test_files/new_world.sav").unwrap();
+        SaveFile::parse_header(&mut file).unwrap();
+        let mut reader = ChunkedZLibReader::new(file).unwrap();
+        assert_eq!(reader.body_length(), 7514008);
+        assert_eq!(reader.max_chunk_size(), 0x20000);
+
+        let copied = std::io::copy(&mut reader, &mut std::io::sink());
+        assert_eq!(copied.unwrap(), 7514008);
+        assert_eq!(reader.remaining_body_length(), 0);
+        assert_eq!(reader.read(&mut [0; 1]).unwrap(), 0);
+        assert_eq!(
+            reader.read_u8().unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    /// Returns a body of `length` bytes and the body compressed in chunks of 16 bytes, with the
+    /// compressed data of the second chunk overwritten.
+    fn corrupt_body(length: usize) -> (Vec<u8>, Vec<u8>) {
+        use crate::zlib_writer::ChunkedZLibWriter;
+
+        let body: Vec<u8> = (1..=length as u8).collect();
+        let mut writer = ChunkedZLibWriter::with_chunk_size(Vec::new(), 16);
+        writer.write_i32::<L>(body.len() as i32).unwrap();
+        writer.write_all(&body).unwrap();
+        let mut data = writer.finish().unwrap();
+
+        let compressed_length = (&data[16..]).read_i64::<L>().unwrap() as usize;
+        let second_chunk = 48 + compressed_length;
+        data[second_chunk + 48..second_chunk + 56].fill(0xff);
+        (body, data)
+    }
+
+    #[test]
+    fn skip_bad_chunks() {
+        let (body, data) = corrupt_body(40);
+
+        let mut read = vec![0; body.len()];
+        let mut reader = ChunkedZLibReader::new(data.as_slice()).unwrap();
+        assert!(reader.read_exact(&mut read).is_err());
 
-        result
+        let mut reader = ChunkedZLibReader::new(data.as_slice()).unwrap();
+        reader.set_skip_bad_chunks(true);
+        reader.read_exact(&mut read).unwrap();
+        assert_eq!(
+            reader.skipped_chunks(),
+            [SkippedChunk {
+                index: 1,
+                body_offset: 12,
+                length: 16,
+            }]
+        );
+        assert_eq!(read[..12], body[..12]);
+        assert_eq!(read[12..28], [0; 16]);
+        assert_eq!(read[28..], body[28..]);
+        assert_eq!(reader.chunk_count(), 3);
     }
 }
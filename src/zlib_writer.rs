@@ -0,0 +1,176 @@
+use super::*;
+use crate::zlib_reader::PACKAGE_FILE_TAG;
+use byteorder::WriteBytesExt;
+use flate2::write::ZlibEncoder;
+use std::io::Write;
+
+/// Writes the zlib compressed parts of the file. Written data is split into chunks of
+/// `chunk_size` bytes, each compressed separately and preceded by a chunk header.
+///
+/// Unlike `ChunkedZLibReader`, this does not handle the body length prefix; write it as part of the
+/// data. Call `finish()` to write the last chunk.
+#[derive(Debug)]
+pub struct ChunkedZLibWriter<W>
+where
+    W: Write,
+{
+    file: W,
+    chunk_size: usize,
+    level: Compression,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> ChunkedZLibWriter<W> {
+    /// Chunk size used by the game.
+    pub const DEFAULT_CHUNK_SIZE: usize = 0x20000;
+
+    pub fn new(file: W) -> Self {
+        ChunkedZLibWriter::with_chunk_size(file, ChunkedZLibWriter::<W>::DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Panics if `chunk_size` is 0.
+    pub fn with_chunk_size(file: W, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be 0");
+        Self {
+            file,
+            chunk_size,
+            level: Compression::default(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Compresses with `level` instead of the default level, which is the level the game uses.
+    pub fn with_level(file: W, level: Compression) -> Self {
+        Self {
+            level,
+            ..ChunkedZLibWriter::new(file)
+        }
+    }
+
+    /// Writes the remaining buffered data as the last chunk and returns the inner writer.
+    pub fn finish(mut self) -> Result<W> {
+        if !self.buffer.is_empty() {
+            let data = std::mem::take(&mut self.buffer);
+            self.write_chunk(&data)?;
+        }
+        self.file.flush()?;
+        Ok(self.file)
+    }
+
+    fn write_chunk(&mut self, data: &[u8]) -> std::io::Result<()> {
+        write_chunk(&mut self.file, data, self.chunk_size, self.level)
+    }
+}
+
+/// Writes `data` as one chunk with its header. `data` must not be longer than `max_chunk_size`.
+pub(crate) fn write_chunk<W>(
+    file: &mut W,
+    data: &[u8],
+    max_chunk_size: usize,
+    compression: Compression,
+) -> std::io::Result<()>
+where
+    W: Write,
+{
+    let mut encoder = ZlibEncoder::new(Vec::new(), compression);
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+
+    let compressed_length = compressed.len() as i64;
+    let uncompressed_length = data.len() as i64;
+    file.write_i64::<L>(PACKAGE_FILE_TAG)?;
+    file.write_i64::<L>(max_chunk_size as i64)?;
+    for _ in 0..2 {
+        file.write_i64::<L>(compressed_length)?;
+        file.write_i64::<L>(uncompressed_length)?;
+    }
+    file.write_all(&compressed)
+}
+
+impl<W: Write> Write for ChunkedZLibWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let length = buf.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..length]);
+        if self.buffer.len() == self.chunk_size {
+            let data = std::mem::take(&mut self.buffer);
+            self.write_chunk(&data)?;
+        }
+        Ok(length)
+    }
+
+    /// Does not write the buffered partial chunk, because that would end the chunk early.
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zlib_reader::ChunkedZLibReader;
+
+    fn compress(data: &[u8], chunk_size: usize) -> Vec<u8> {
+        let mut writer = ChunkedZLibWriter::with_chunk_size(Vec::new(), chunk_size);
+        writer
+            .write_u32::<L>(data.len().try_into().unwrap())
+            .unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn chunk_size() {
+        let data: Vec<u8> = (0..1000).map(|n| (n % 251) as u8).collect();
+        let compressed = compress(&data, 0x100);
+
+        let mut reader = ChunkedZLibReader::new(compressed.as_slice()).unwrap();
+        let mut result = Vec::new();
+        (&mut reader).take(1000).read_to_end(&mut result).unwrap();
+        assert_eq!(result, data);
+        assert_eq!(reader.chunk_count(), 4); // 1004 bytes including length prefix
+        assert_eq!(reader.max_chunk_size(), 0x100);
+
+        let compressed = compress(&data, ChunkedZLibWriter::<Vec<u8>>::DEFAULT_CHUNK_SIZE);
+        let mut reader = ChunkedZLibReader::new(compressed.as_slice()).unwrap();
+        let mut result = Vec::new();
+        (&mut reader).take(1000).read_to_end(&mut result).unwrap();
+        assert_eq!(result, data);
+        assert_eq!(reader.chunk_count(), 1);
+    }
+
+    #[test]
+    fn level() {
+        let data: Vec<u8> = (0..0x1000).map(|n| (n % 7 * n % 13) as u8).collect();
+        let mut sizes = Vec::new();
+        for level in [
+            Compression::none(),
+            Compression::fast(),
+            Compression::best(),
+        ] {
+            let mut writer = ChunkedZLibWriter::with_level(Vec::new(), level);
+            writer.write_u32::<L>(data.len() as u32).unwrap();
+            writer.write_all(&data).unwrap();
+            let compressed = writer.finish().unwrap();
+
+            let mut reader = ChunkedZLibReader::new(compressed.as_slice()).unwrap();
+            let mut result = vec![0; data.len()];
+            reader.read_exact(&mut result).unwrap();
+            assert_eq!(result, data);
+            sizes.push(compressed.len());
+        }
+        assert!(sizes[0] > sizes[1]);
+        assert!(sizes[1] >= sizes[2]);
+    }
+
+    #[test]
+    fn chunk_exceeds_max_chunk_size() {
+        let mut compressed = compress(&[0; 100], 0x100);
+        // Lower max chunk size in the first header
+        compressed[8..16].copy_from_slice(&0x10_i64.to_le_bytes());
+        let error = ChunkedZLibReader::new(compressed.as_slice()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "chunk length 104 exceeds max chunk size 16"
+        );
+    }
+}